@@ -0,0 +1,319 @@
+use std::{
+    collections::HashMap,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+use cpal::{
+    BufferSize, Device, Host, SampleFormat, SampleRate, StreamConfig, SupportedBufferSize,
+    SupportedStreamConfigRange,
+    traits::{DeviceTrait as _, HostTrait as _},
+};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter as _, Manager as _};
+
+use crate::{audio_engine::AudioEngine, audio_host};
+
+// デバイスの増減を調べる間隔
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// デバイスが揃っているのに繋ぎ直しが続けて失敗したら、間隔を倍にしながらこの回数まで試す
+const MAX_RECONNECT_ATTEMPTS: u32 = 5;
+
+// バッファサイズを指定しなかったときのフレーム数。デバイスが対応していなければ cpal に任せる
+const DEFAULT_BUFFER_SIZE: u32 = 480;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeviceDirection {
+    Input,
+    Output,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioDevice {
+    /// ホスト名、デバイス名、同じ名前のデバイスの中での順番から作る ID
+    pub id: String,
+    pub host: String,
+    pub name: String,
+    pub direction: DeviceDirection,
+}
+
+/// "audio-devices-changed" イベントで送る、前回から増えたデバイスと減ったデバイス
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioDevicesChanged {
+    pub added: Vec<AudioDevice>,
+    pub removed: Vec<AudioDevice>,
+}
+
+/// ストリームの設定。`None` はデバイスの既定値
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct StreamSettings {
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    /// 1 回のコールバックのフレーム数
+    pub buffer_size: Option<u32>,
+}
+
+/// デバイスが対応している設定の範囲 (`SupportedStreamConfigRange` のうち UI に要るところ)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SupportedConfig {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    /// デバイスから分からなければ `None`
+    pub min_buffer_size: Option<u32>,
+    pub max_buffer_size: Option<u32>,
+}
+
+fn device_id(host_name: &str, device_name: &str, index: usize) -> String {
+    format!("{host_name}:{device_name}:{index}")
+}
+
+/// `host` のデバイスを 1 度だけ列挙し、ID を付けて返す
+pub fn list_devices(
+    host: &Host,
+    direction: DeviceDirection,
+) -> anyhow::Result<Vec<(AudioDevice, Device)>> {
+    let devices = match direction {
+        DeviceDirection::Input => host.input_devices()?.collect::<Vec<_>>(),
+        DeviceDirection::Output => host.output_devices()?.collect::<Vec<_>>(),
+    };
+
+    let host_name = host.id().name();
+    let mut name_counts = HashMap::<String, usize>::new();
+
+    let devices = devices
+        .into_iter()
+        .map(|device| {
+            let name = device.name().unwrap_or_default();
+
+            let count = name_counts.entry(name.clone()).or_default();
+            let id = device_id(host_name, &name, *count);
+            *count += 1;
+
+            let info = AudioDevice {
+                id,
+                host: host_name.to_string(),
+                name,
+                direction,
+            };
+
+            (info, device)
+        })
+        .collect();
+
+    Ok(devices)
+}
+
+pub fn find_device(
+    host: &Host,
+    direction: DeviceDirection,
+    device_id: &str,
+) -> anyhow::Result<Device> {
+    list_devices(host, direction)?
+        .into_iter()
+        .find(|(info, _)| info.id == device_id)
+        .map(|(_, device)| device)
+        .with_context(|| format!("device not found: {device_id}"))
+}
+
+// ストリームは f32 で作るので、f32 の設定だけを返す
+fn supported_f32_configs(
+    device: &Device,
+    direction: DeviceDirection,
+) -> anyhow::Result<Vec<SupportedStreamConfigRange>> {
+    let configs = match direction {
+        DeviceDirection::Input => device.supported_input_configs()?.collect::<Vec<_>>(),
+        DeviceDirection::Output => device.supported_output_configs()?.collect::<Vec<_>>(),
+    };
+
+    Ok(configs
+        .into_iter()
+        .filter(|config| config.sample_format() == SampleFormat::F32)
+        .collect())
+}
+
+pub fn supported_configs(
+    device: &Device,
+    direction: DeviceDirection,
+) -> anyhow::Result<Vec<SupportedConfig>> {
+    let configs = supported_f32_configs(device, direction)?
+        .into_iter()
+        .map(|config| {
+            let (min_buffer_size, max_buffer_size) = match *config.buffer_size() {
+                SupportedBufferSize::Range { min, max } => (Some(min), Some(max)),
+                SupportedBufferSize::Unknown => (None, None),
+            };
+
+            SupportedConfig {
+                channels: config.channels(),
+                min_sample_rate: config.min_sample_rate().0,
+                max_sample_rate: config.max_sample_rate().0,
+                min_buffer_size,
+                max_buffer_size,
+            }
+        })
+        .collect();
+
+    Ok(configs)
+}
+
+/// `settings` の空いているところを既定値で埋め、デバイスが対応している設定か確かめる
+pub fn resolve_stream_config(
+    device: &Device,
+    direction: DeviceDirection,
+    settings: &StreamSettings,
+) -> anyhow::Result<StreamConfig> {
+    let default_config = match direction {
+        DeviceDirection::Input => device.default_input_config()?,
+        DeviceDirection::Output => device.default_output_config()?,
+    };
+
+    let channels = settings.channels.unwrap_or(default_config.channels());
+    let sample_rate = settings
+        .sample_rate
+        .map(SampleRate)
+        .unwrap_or(default_config.sample_rate());
+
+    let ranges = supported_f32_configs(device, direction)?
+        .into_iter()
+        .filter(|range| {
+            range.channels() == channels
+                && range.min_sample_rate() <= sample_rate
+                && sample_rate <= range.max_sample_rate()
+        })
+        .collect::<Vec<_>>();
+
+    if ranges.is_empty() {
+        anyhow::bail!(
+            "unsupported stream config: {channels} ch, {} Hz",
+            sample_rate.0
+        );
+    }
+
+    let supports_buffer_size = |frames: u32| {
+        ranges.iter().any(|range| match *range.buffer_size() {
+            SupportedBufferSize::Range { min, max } => (min..=max).contains(&frames),
+            SupportedBufferSize::Unknown => true,
+        })
+    };
+
+    let buffer_size = match settings.buffer_size {
+        Some(frames) if supports_buffer_size(frames) => BufferSize::Fixed(frames),
+        Some(frames) => anyhow::bail!("unsupported buffer size: {frames} frames"),
+        None if supports_buffer_size(DEFAULT_BUFFER_SIZE) => BufferSize::Fixed(DEFAULT_BUFFER_SIZE),
+        None => BufferSize::Default,
+    };
+
+    Ok(StreamConfig {
+        channels,
+        sample_rate,
+        buffer_size,
+    })
+}
+
+// `host_name` のホストの入力・出力デバイス。ホストが開けなければ空
+fn host_devices(host_name: Option<&str>) -> Vec<AudioDevice> {
+    let Ok(host) = audio_host::open_host(host_name) else {
+        return vec![];
+    };
+
+    let mut devices = vec![];
+    for direction in [DeviceDirection::Input, DeviceDirection::Output] {
+        if let Ok(list) = list_devices(&host, direction) {
+            devices.extend(list.into_iter().map(|(info, _)| info));
+        }
+    }
+
+    devices
+}
+
+/// デバイスの増減を見張るスレッドを立ち上げる
+///
+/// 増減があれば "audio-devices-changed" を送り、切断で止まったボイスチェンジャーは
+/// 選んでいたデバイスがすべて揃った時点で繋ぎ直して "audio-engine-reconnected" を送る
+///
+/// 列挙は遅いホストもあるので、見るのはボイスチェンジャーに設定したホストだけにする
+pub fn spawn_device_watcher(app_handle: AppHandle) {
+    thread::spawn(move || {
+        let engine = app_handle.state::<AudioEngine>();
+        let mut previous = host_devices(engine.host_name().as_deref());
+
+        let mut reconnect_failures = 0;
+        let mut next_reconnect = Instant::now();
+
+        loop {
+            thread::sleep(DEVICE_POLL_INTERVAL);
+
+            let current = host_devices(engine.host_name().as_deref());
+
+            if current != previous {
+                let changed = AudioDevicesChanged {
+                    added: current
+                        .iter()
+                        .filter(|device| !previous.contains(device))
+                        .cloned()
+                        .collect(),
+                    removed: previous
+                        .iter()
+                        .filter(|device| !current.contains(device))
+                        .cloned()
+                        .collect(),
+                };
+
+                let _ = app_handle.emit("audio-devices-changed", changed);
+                previous = current;
+            }
+
+            let Some(config) = engine.disconnected_config() else {
+                reconnect_failures = 0;
+                continue;
+            };
+
+            if Instant::now() < next_reconnect {
+                continue;
+            }
+
+            let is_available = |direction, device_id: &str| {
+                previous
+                    .iter()
+                    .any(|device| device.direction == direction && device.id == device_id)
+            };
+
+            if config
+                .input_device_id
+                .as_deref()
+                .is_some_and(|id| is_available(DeviceDirection::Input, id))
+                && config
+                    .output_device_id
+                    .as_deref()
+                    .is_some_and(|id| is_available(DeviceDirection::Output, id))
+                && config
+                    .monitor_device_id()
+                    .is_none_or(|id| is_available(DeviceDirection::Output, id))
+            {
+                match engine.reconnect(&app_handle) {
+                    Ok(true) => {
+                        reconnect_failures = 0;
+                        let _ = app_handle.emit("audio-engine-reconnected", ());
+                    }
+                    Ok(false) => {}
+                    Err(err) => {
+                        eprintln!("再接続エラー: {err}");
+
+                        // モデルや設定の問題なら何度やっても失敗するので、いずれ諦める
+                        reconnect_failures += 1;
+                        if MAX_RECONNECT_ATTEMPTS <= reconnect_failures {
+                            engine.give_up_reconnect(&err);
+                            reconnect_failures = 0;
+                        } else {
+                            next_reconnect = Instant::now()
+                                + DEVICE_POLL_INTERVAL * 2_u32.pow(reconnect_failures);
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,721 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        mpsc::{self, RecvTimeoutError, TryRecvError},
+    },
+    thread::{self, JoinHandle, Thread},
+    time::Duration,
+};
+
+use anyhow::Context as _;
+use beatrice_lib::{Beatrice, ChannelMix, Downmix, VoiceParameters};
+use cpal::{
+    Stream, StreamError,
+    traits::{DeviceTrait as _, StreamTrait as _},
+};
+use ringbuf::{
+    HeapCons, HeapProd, HeapRb,
+    traits::{Consumer as _, Observer as _, Producer as _, Split as _},
+};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter as _};
+
+use crate::{
+    audio_device::{self, DeviceDirection, StreamSettings},
+    audio_host,
+};
+
+/// f32 をビット列のまま入れておく atomic
+pub struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    pub const fn new(value: f32) -> Self {
+        Self(AtomicU32::new(value.to_bits()))
+    }
+
+    pub fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+pub static INPUT_GAIN: AtomicF32 = AtomicF32::new(1.0);
+pub static OUTPUT_GAIN: AtomicF32 = AtomicF32::new(1.0);
+pub static MONITOR_GAIN: AtomicF32 = AtomicF32::new(1.0);
+pub static INPUT_THRESHOLD: AtomicF32 = AtomicF32::new(1.0);
+pub static MIC_LEVEL: AtomicF32 = AtomicF32::new(1.0);
+
+/// Beatrice を作り直しても引き継ぐため、ピッチの情報を送るかどうかはここに持つ
+pub static PITCH_TELEMETRY: AtomicBool = AtomicBool::new(false);
+
+// 推論スレッドが止まったときのモデルと声の設定。同じモデルで起動し直したら引き継ぐ
+static VOICE_PARAMETERS: Mutex<Option<(PathBuf, VoiceParameters)>> = Mutex::new(None);
+
+type BeatriceCommand = Box<dyn FnOnce(&mut dyn Beatrice) + Send>;
+
+// 動いている推論スレッドへの入口
+struct WorkerEntry {
+    commands: mpsc::Sender<BeatriceCommand>,
+    // コマンドを送ったら unpark で起こす
+    thread: Thread,
+}
+
+// 差し替えると前の推論スレッドは止まる
+static BEATRICE_COMMANDS: Mutex<Option<WorkerEntry>> = Mutex::new(None);
+
+// 推論スレッドは入力のコールバックかコマンドで起こされる。これは起こし損ねたときの保険
+const WORKER_IDLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+// 推論が詰まっていても UI のコマンドが返ってこなくならないよう、これ以上は待たない
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// `with_beatrice` が推論スレッドから結果を受け取れなかった理由
+#[derive(Debug, thiserror::Error)]
+pub enum CommandError {
+    /// 推論スレッドが動いていない。モデルを読み込んで起動するまではこれになる
+    #[error("ModelNotLoaded")]
+    NotRunning,
+
+    /// 推論が詰まっていて `COMMAND_TIMEOUT` 以内に返らなかった
+    #[error("CommandTimeout")]
+    Timeout,
+}
+
+/// 推論スレッドで `f` を実行して結果を待つ
+pub fn with_beatrice<T: Send + 'static>(
+    f: impl FnOnce(&mut dyn Beatrice) -> T + Send + 'static,
+) -> Result<T, CommandError> {
+    let (reply_sender, reply_receiver) = mpsc::sync_channel(1);
+    let command: BeatriceCommand = Box::new(move |beatrice| {
+        let _ = reply_sender.send(f(beatrice));
+    });
+
+    {
+        let entry = BEATRICE_COMMANDS.lock().unwrap();
+        let entry = entry.as_ref().ok_or(CommandError::NotRunning)?;
+        entry
+            .commands
+            .send(command)
+            .map_err(|_| CommandError::NotRunning)?;
+        entry.thread.unpark();
+    }
+
+    reply_receiver
+        .recv_timeout(COMMAND_TIMEOUT)
+        .map_err(|err| match err {
+            RecvTimeoutError::Timeout => CommandError::Timeout,
+            // 実行する前に推論スレッドが止まった
+            RecvTimeoutError::Disconnected => CommandError::NotRunning,
+        })
+}
+
+/// デバイスのコールバックとリングバッファでつながる推論スレッド
+///
+/// コールバックはサンプルを移して `WorkerHandle::waker` で起こすだけで、
+/// ゲインの適用、入力のしきい値、推論はすべてここで行う
+pub struct InferenceWorker {
+    pub beatrice: Box<dyn Beatrice>,
+    pub input: HeapCons<f32>,
+    pub output: HeapProd<f32>,
+    pub monitor: HeapProd<f32>,
+    pub in_sample_rate: u32,
+    pub in_channel: u16,
+    /// マイクのレベルを測るチャンネル。`None` ならすべてのチャンネル
+    ///
+    /// 推論に使うチャンネルは Beatrice の `ChannelMix` で選ぶので、ここでは測るだけ
+    pub input_channel: Option<u16>,
+    pub out_sample_rate: u32,
+    pub out_channel: u16,
+}
+
+impl InferenceWorker {
+    /// 推論スレッドを立ち上げ、`with_beatrice` の行き先をこのスレッドに差し替える
+    pub fn spawn(mut self) -> WorkerHandle {
+        self.beatrice
+            .set_pitch_telemetry(PITCH_TELEMETRY.load(Ordering::Relaxed));
+
+        if let Some((model_path, parameters)) = VOICE_PARAMETERS.lock().unwrap().take()
+            && self.beatrice.get_model_path() == Some(model_path.as_path())
+        {
+            restore_parameters(self.beatrice.as_mut(), &parameters);
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::spawn(move || self.run(receiver));
+        {
+            let mut commands = BEATRICE_COMMANDS.lock().unwrap();
+            *commands = Some(WorkerEntry {
+                commands: sender,
+                thread: thread.thread().clone(),
+            });
+        }
+
+        WorkerHandle { thread }
+    }
+
+    fn run(mut self, receiver: mpsc::Receiver<BeatriceCommand>) {
+        let block_frames = (self.in_sample_rate as f64 / 100.0).round() as usize;
+        let mut block = vec![0.0_f32; block_frames * self.in_channel as usize];
+
+        // 無音にした分の出力のフレーム数の端数
+        let mut silent_frames = 0.0_f64;
+
+        'worker: loop {
+            loop {
+                match receiver.try_recv() {
+                    Ok(command) => command(self.beatrice.as_mut()),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => break 'worker,
+                }
+            }
+
+            while block.len() <= self.input.occupied_len() {
+                self.input.pop_slice(&mut block);
+
+                let input_gain = INPUT_GAIN.load();
+                for v in block.iter_mut() {
+                    *v *= input_gain;
+                }
+
+                let (skip, step) = match self.input_channel {
+                    Some(channel) => (channel as usize, self.in_channel as usize),
+                    None => (0, 1),
+                };
+                let measured = block.iter().skip(skip).step_by(step);
+                let n_measured = measured.len();
+                let sum_squares: f32 = measured.map(|v| v * v).sum();
+                let mic_level = (sum_squares / n_measured as f32).sqrt().powf(0.3);
+                MIC_LEVEL.store(mic_level);
+
+                let result = match INPUT_THRESHOLD.load() < mic_level {
+                    true => self.beatrice.infer(&block).ok(),
+                    false => None,
+                };
+
+                match result {
+                    Some(result) => {
+                        self.output.push_slice(&result);
+                        self.monitor.push_slice(&result);
+                    }
+                    None => {
+                        silent_frames += block_frames as f64 * self.out_sample_rate as f64
+                            / self.in_sample_rate as f64;
+                        let frames = silent_frames.floor();
+                        silent_frames -= frames;
+
+                        let samples = frames as usize * self.out_channel as usize;
+                        self.output.push_iter(std::iter::repeat_n(0.0, samples));
+                        self.monitor.push_iter(std::iter::repeat_n(0.0, samples));
+                    }
+                }
+            }
+
+            thread::park_timeout(WORKER_IDLE_TIMEOUT);
+        }
+
+        if let Some(model_path) = self.beatrice.get_model_path() {
+            *VOICE_PARAMETERS.lock().unwrap() =
+                Some((model_path.to_path_buf(), self.beatrice.get_parameters()));
+        }
+    }
+}
+
+// 前の推論スレッドの設定を戻す。同じモデルで取った値なので、失敗しても無視する
+fn restore_parameters(beatrice: &mut dyn Beatrice, parameters: &VoiceParameters) {
+    let _ = beatrice.set_speaker_mix(&parameters.speaker_mix);
+    let _ = beatrice.set_formant_shift(parameters.formant_shift);
+    let _ = beatrice.set_average_source_pitch(parameters.average_source_pitch);
+    let _ = beatrice.set_intonation_intensity(parameters.intonation_intensity);
+    let _ = beatrice.set_pitch_correction(parameters.pitch_correction);
+    let _ = beatrice.set_pitch_correction_mode(parameters.pitch_correction_mode);
+    let _ = beatrice.set_pitch_glide_time(parameters.pitch_glide_time);
+    let _ = beatrice.set_pitch_median_window(parameters.pitch_median_window);
+    let _ = beatrice.set_pitch_hysteresis(parameters.pitch_hysteresis);
+    if let Some(min_source_pitch) = parameters.min_source_pitch {
+        let _ = beatrice.set_min_source_pitch(min_source_pitch);
+    }
+    if let Some(max_source_pitch) = parameters.max_source_pitch {
+        let _ = beatrice.set_max_source_pitch(max_source_pitch);
+    }
+    if let Some(vq_num_neighbors) = parameters.vq_num_neighbors {
+        let _ = beatrice.set_vq_num_neighbors(vq_num_neighbors);
+    }
+    // 自動のピッチシフトが有効なら、話者と平均ピッチが決まってから決め直される
+    let _ = beatrice.set_pitch_shift(parameters.pitch_shift);
+    let _ = beatrice.set_auto_pitch_shift(parameters.auto_pitch_shift);
+}
+
+pub struct WorkerHandle {
+    thread: JoinHandle<()>,
+}
+
+impl WorkerHandle {
+    /// 入力のコールバックが推論スレッドを起こすのに使う
+    pub fn waker(&self) -> Thread {
+        self.thread.thread().clone()
+    }
+
+    /// `with_beatrice` の入口を閉じ、推論スレッドが終わるのを待つ
+    pub fn stop(self) {
+        {
+            let mut commands = BEATRICE_COMMANDS.lock().unwrap();
+            *commands = None;
+        }
+
+        self.thread.thread().unpark();
+        let _ = self.thread.join();
+    }
+}
+
+/// 出力のコールバック用。溜まっている分を取り出し、足りない分は無音にしてゲインを掛ける
+pub fn pop_output(consumer: &mut HeapCons<f32>, data: &mut [f32], gain: &AtomicF32) {
+    let popped = consumer.pop_slice(data);
+    data[popped..].fill(0.0);
+
+    let gain = gain.load();
+    for v in data.iter_mut() {
+        *v *= gain;
+    }
+}
+
+/// ボイスチェンジャーを動かすのに必要な設定。`restart` では最後に使ったものを使い回す
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioEngineConfig {
+    pub model_path: String,
+    /// cpal のホスト名。`None` なら既定のホスト
+    pub host_name: Option<String>,
+    /// `audio_device::list_devices` で付けたデバイスの ID
+    pub input_device_id: Option<String>,
+    pub output_device_id: Option<String>,
+    /// `None` または "None" ならモニターしない
+    pub monitor_device_id: Option<String>,
+    pub input_stream: StreamSettings,
+    /// モニターも出力と同じサンプリングレートとチャンネル数で動かす
+    pub output_stream: StreamSettings,
+    /// マイクの入っている入力チャンネル (0 始まり)。`None` ならすべてのチャンネルを使う
+    pub input_channel: Option<u16>,
+}
+
+impl AudioEngineConfig {
+    pub fn monitor_device_id(&self) -> Option<&str> {
+        self.monitor_device_id
+            .as_deref()
+            .filter(|device_id| *device_id != "None")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum AudioEngineStatus {
+    #[default]
+    Stopped,
+    Running,
+    /// 起動に失敗した
+    Failed(String),
+    /// 動いている途中でデバイスが外れた。デバイスが戻れば繋ぎ直す
+    Disconnected(String),
+}
+
+// エンジンのスレッドに届くもの
+enum EngineEvent {
+    Stop,
+    StreamDied(String),
+}
+
+// ストリームが止まったときに "audio-stream-died" イベントで送る
+const STREAM_DIED_EVENT: &str = "audio-stream-died";
+
+// mic-level と pitch-telemetry を送る間隔
+const LEVEL_EMIT_INTERVAL: Duration = Duration::from_millis(50);
+
+struct RunningEngine {
+    events: mpsc::Sender<EngineEvent>,
+    thread: JoinHandle<()>,
+}
+
+// `AudioEngine::running` のロックの中身
+#[derive(Default)]
+struct EngineSlot {
+    engine: Option<RunningEngine>,
+    // `stop` で止めた。`reconnect` は同じロックの中でこれを見て、起動し直さない
+    stopped: bool,
+}
+
+/// デバイスのストリームと推論スレッドをまとめて起動・停止する。Tauri の managed state に置く
+///
+/// cpal のストリームは Send でない環境があるので、ストリームは起動ごとに作るスレッドの中で持つ
+#[derive(Default)]
+pub struct AudioEngine {
+    running: Mutex<EngineSlot>,
+    config: Mutex<Option<AudioEngineConfig>>,
+    status: Arc<Mutex<AudioEngineStatus>>,
+}
+
+impl AudioEngine {
+    /// 動いていれば止めてから起動する。デバイスが見つからない、モデルが読めないなどの失敗はここで返る
+    pub fn start(&self, app_handle: &AppHandle, config: AudioEngineConfig) -> anyhow::Result<()> {
+        let mut running = self.running.lock().unwrap();
+        running.stopped = false;
+
+        self.start_locked(&mut running, app_handle, config)
+    }
+
+    // `running` のロックを取ったまま起動する
+    fn start_locked(
+        &self,
+        running: &mut EngineSlot,
+        app_handle: &AppHandle,
+        config: AudioEngineConfig,
+    ) -> anyhow::Result<()> {
+        Self::stop_running(running.engine.take());
+
+        *self.config.lock().unwrap() = Some(config.clone());
+
+        let (events_sender, events_receiver) = mpsc::channel();
+        let (ready_sender, ready_receiver) = mpsc::sync_channel(1);
+
+        let thread = {
+            let app_handle = app_handle.clone();
+            let events_sender = events_sender.clone();
+            let status = self.status.clone();
+
+            thread::spawn(move || {
+                run_engine(
+                    app_handle,
+                    config,
+                    events_sender,
+                    events_receiver,
+                    ready_sender,
+                    status,
+                )
+            })
+        };
+
+        match ready_receiver.recv() {
+            Ok(Ok(())) => {
+                *self.status.lock().unwrap() = AudioEngineStatus::Running;
+                running.engine = Some(RunningEngine {
+                    events: events_sender,
+                    thread,
+                });
+                Ok(())
+            }
+            Ok(Err(err)) => {
+                let _ = thread.join();
+                *self.status.lock().unwrap() = AudioEngineStatus::Failed(err.to_string());
+                Err(err)
+            }
+            Err(_) => {
+                let _ = thread.join();
+                let message = "audio engine thread panicked".to_string();
+                *self.status.lock().unwrap() = AudioEngineStatus::Failed(message.clone());
+                Err(anyhow::anyhow!(message))
+            }
+        }
+    }
+
+    pub fn stop(&self) {
+        let mut running = self.running.lock().unwrap();
+        running.stopped = true;
+        Self::stop_running(running.engine.take());
+
+        *self.status.lock().unwrap() = AudioEngineStatus::Stopped;
+    }
+
+    /// 最後に `start` したときの設定で起動し直す
+    pub fn restart(&self, app_handle: &AppHandle) -> anyhow::Result<()> {
+        let config = self
+            .config
+            .lock()
+            .unwrap()
+            .clone()
+            .context("voice changer has never been started")?;
+
+        self.start(app_handle, config)
+    }
+
+    /// 最後に `start` したときのホスト名。まだ起動していないか既定のホストなら `None`
+    pub fn host_name(&self) -> Option<String> {
+        self.config.lock().unwrap().as_ref()?.host_name.clone()
+    }
+
+    pub fn status(&self) -> AudioEngineStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// デバイスが外れて止まっているなら、そのときの設定
+    pub fn disconnected_config(&self) -> Option<AudioEngineConfig> {
+        match *self.status.lock().unwrap() {
+            AudioEngineStatus::Disconnected(_) => self.config.lock().unwrap().clone(),
+            _ => None,
+        }
+    }
+
+    /// 外れたデバイスが戻ったときに起動し直す。`stop` で止められていたら何もせず `Ok(false)`
+    ///
+    /// 失敗しても次に戻ったときに繋ぎ直せるよう切断扱いのままにする
+    pub fn reconnect(&self, app_handle: &AppHandle) -> anyhow::Result<bool> {
+        let mut running = self.running.lock().unwrap();
+        if running.stopped {
+            return Ok(false);
+        }
+
+        let config = self
+            .config
+            .lock()
+            .unwrap()
+            .clone()
+            .context("voice changer has never been started")?;
+
+        self.start_locked(&mut running, app_handle, config)
+            .inspect_err(|err| {
+                *self.status.lock().unwrap() = AudioEngineStatus::Disconnected(err.to_string());
+            })?;
+
+        Ok(true)
+    }
+
+    /// 繋ぎ直しを諦めて失敗扱いにする。その間に `stop` や `start` されていれば何もしない
+    pub fn give_up_reconnect(&self, err: &anyhow::Error) {
+        let running = self.running.lock().unwrap();
+        let mut status = self.status.lock().unwrap();
+
+        if !running.stopped && matches!(*status, AudioEngineStatus::Disconnected(_)) {
+            *status = AudioEngineStatus::Failed(err.to_string());
+        }
+    }
+
+    fn stop_running(running: Option<RunningEngine>) {
+        if let Some(running) = running {
+            let _ = running.events.send(EngineEvent::Stop);
+            let _ = running.thread.join();
+        }
+    }
+}
+
+struct EngineStreams {
+    input: Stream,
+    output: Stream,
+    monitor: Option<Stream>,
+    worker: WorkerHandle,
+}
+
+fn run_engine(
+    app_handle: AppHandle,
+    config: AudioEngineConfig,
+    events_sender: mpsc::Sender<EngineEvent>,
+    events_receiver: mpsc::Receiver<EngineEvent>,
+    ready: mpsc::SyncSender<anyhow::Result<()>>,
+    status: Arc<Mutex<AudioEngineStatus>>,
+) {
+    let streams = match build_streams(&config, &events_sender) {
+        Ok(streams) => streams,
+        Err(err) => {
+            let _ = ready.send(Err(err));
+            return;
+        }
+    };
+    let _ = ready.send(Ok(()));
+
+    loop {
+        match events_receiver.recv_timeout(LEVEL_EMIT_INTERVAL) {
+            Ok(EngineEvent::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+            Ok(EngineEvent::StreamDied(message)) => {
+                *status.lock().unwrap() = AudioEngineStatus::Disconnected(message.clone());
+                let _ = app_handle.emit(STREAM_DIED_EVENT, message);
+                break;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+
+        let _ = app_handle.emit("mic-level", MIC_LEVEL.load());
+
+        if PITCH_TELEMETRY.load(Ordering::Relaxed) {
+            let pitch_telemetry =
+                with_beatrice(|beatrice| beatrice.take_pitch_telemetry()).unwrap_or_default();
+
+            if !pitch_telemetry.is_empty() {
+                let _ = app_handle.emit("pitch-telemetry", pitch_telemetry);
+            }
+        }
+    }
+
+    // 推論スレッドより先にコールバックを止める
+    drop(streams.input);
+    drop(streams.output);
+    drop(streams.monitor);
+    streams.worker.stop();
+}
+
+fn build_streams(
+    config: &AudioEngineConfig,
+    events: &mpsc::Sender<EngineEvent>,
+) -> anyhow::Result<EngineStreams> {
+    let input_device_id = config
+        .input_device_id
+        .as_deref()
+        .context("input device is not selected")?;
+    let output_device_id = config
+        .output_device_id
+        .as_deref()
+        .context("output device is not selected")?;
+
+    let ring_size = 4096;
+
+    // 入力・出力・モニターはすべて同じホストから探す
+    let host = audio_host::open_host(config.host_name.as_deref())?;
+
+    // input
+    let input_device = audio_device::find_device(&host, DeviceDirection::Input, input_device_id)?;
+    let input_config = audio_device::resolve_stream_config(
+        &input_device,
+        DeviceDirection::Input,
+        &config.input_stream,
+    )?;
+
+    if let Some(channel) = config.input_channel
+        && input_config.channels <= channel
+    {
+        anyhow::bail!(
+            "input channel {channel} is out of range ({} ch)",
+            input_config.channels
+        );
+    }
+
+    // output
+    let output_device =
+        audio_device::find_device(&host, DeviceDirection::Output, output_device_id)?;
+    let output_config = audio_device::resolve_stream_config(
+        &output_device,
+        DeviceDirection::Output,
+        &config.output_stream,
+    )?;
+
+    // monitor
+    let monitor = match config.monitor_device_id() {
+        Some(device_id) => {
+            let device = audio_device::find_device(&host, DeviceDirection::Output, device_id)?;
+            let monitor_settings = StreamSettings {
+                sample_rate: Some(output_config.sample_rate.0),
+                channels: Some(output_config.channels),
+                buffer_size: config.output_stream.buffer_size,
+            };
+            let monitor_config = audio_device::resolve_stream_config(
+                &device,
+                DeviceDirection::Output,
+                &monitor_settings,
+            )?;
+
+            Some((device, monitor_config))
+        }
+        None => None,
+    };
+
+    let mut beatrice = beatrice_lib::new(
+        &config.model_path,
+        input_config.sample_rate.0.into(),
+        output_config.sample_rate.0.into(),
+        input_config.channels.into(),
+        output_config.channels.into(),
+    )?;
+
+    // 入力はインターリーブのまま渡し、マイクのチャンネルの取り出しは Beatrice に任せる
+    if let Some(channel) = config.input_channel {
+        beatrice.set_channel_mix(ChannelMix {
+            downmix: Downmix::Channel(channel.into()),
+            ..Default::default()
+        })?;
+    }
+
+    // 入力は推論スレッドが 10ms ずつ取り出すので、それより十分大きくしておく
+    let input_ring_size =
+        (input_config.sample_rate.0 as usize * input_config.channels as usize / 5).max(ring_size);
+
+    let (mut input_producer, input_consumer) = HeapRb::new(input_ring_size).split();
+    let (output_producer, mut output_consumer) = HeapRb::new(ring_size).split();
+    let (monitor_producer, mut monitor_consumer) = HeapRb::new(ring_size).split();
+
+    let worker = InferenceWorker {
+        beatrice,
+        input: input_consumer,
+        output: output_producer,
+        monitor: monitor_producer,
+        in_sample_rate: input_config.sample_rate.0,
+        in_channel: input_config.channels,
+        input_channel: config.input_channel,
+        out_sample_rate: output_config.sample_rate.0,
+        out_channel: output_config.channels,
+    }
+    .spawn();
+
+    let worker_waker = worker.waker();
+
+    let error_callback = |label: &'static str| {
+        let events = events.clone();
+        move |err: StreamError| {
+            eprintln!("{label}エラー: {err}");
+
+            if matches!(err, StreamError::DeviceNotAvailable) {
+                let _ = events.send(EngineEvent::StreamDied(format!("{label}: {err}")));
+            }
+        }
+    };
+
+    let streams = (|| -> anyhow::Result<(Stream, Stream, Option<Stream>)> {
+        let input_stream = input_device.build_input_stream(
+            &input_config,
+            move |data: &[f32], _: &_| {
+                input_producer.push_slice(data);
+                worker_waker.unpark();
+            },
+            error_callback("入力"),
+            None,
+        )?;
+
+        let output_stream = output_device.build_output_stream(
+            &output_config,
+            move |data: &mut [f32], _: &_| {
+                pop_output(&mut output_consumer, data, &OUTPUT_GAIN);
+            },
+            error_callback("出力"),
+            None,
+        )?;
+
+        let monitor_stream = match monitor {
+            Some((device, monitor_config)) => Some(device.build_output_stream(
+                &monitor_config,
+                move |data: &mut [f32], _: &_| {
+                    pop_output(&mut monitor_consumer, data, &MONITOR_GAIN);
+                },
+                error_callback("モニター"),
+                None,
+            )?),
+            None => None,
+        };
+
+        input_stream.play()?;
+        output_stream.play()?;
+        if let Some(stream) = &monitor_stream {
+            stream.play()?;
+        }
+
+        Ok((input_stream, output_stream, monitor_stream))
+    })();
+
+    match streams {
+        Ok((input, output, monitor)) => Ok(EngineStreams {
+            input,
+            output,
+            monitor,
+            worker,
+        }),
+        Err(err) => {
+            worker.stop();
+            Err(err)
+        }
+    }
+}
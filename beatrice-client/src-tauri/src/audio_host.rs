@@ -0,0 +1,29 @@
+use anyhow::Context as _;
+use cpal::Host;
+
+/// このビルドで使える cpal のホスト名 (ASIO と JACK は cpal の feature を有効にしたときだけ)
+pub fn available_host_names() -> Vec<String> {
+    cpal::available_hosts()
+        .into_iter()
+        .map(|id| id.name().to_string())
+        .collect()
+}
+
+/// OS の既定のホスト名 (Windows なら WASAPI、Linux なら ALSA、macOS なら CoreAudio)
+pub fn default_host_name() -> String {
+    cpal::default_host().id().name().to_string()
+}
+
+/// 名前からホストを開く。`None` なら既定のホスト
+pub fn open_host(host_name: Option<&str>) -> anyhow::Result<Host> {
+    let Some(host_name) = host_name else {
+        return Ok(cpal::default_host());
+    };
+
+    let id = cpal::available_hosts()
+        .into_iter()
+        .find(|id| id.name() == host_name)
+        .with_context(|| format!("audio host not available: {host_name}"))?;
+
+    Ok(cpal::host_from_id(id)?)
+}
@@ -1,9 +1,12 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::atomic::Ordering};
 
-use beatrice_lib::{BeatriceError, BeatriceToml};
+use beatrice_lib::{
+    BeatriceToml, CalibrationState, Capabilities, Latency, PitchCalibration, PitchCorrectionMode,
+    SpeakerWeight, VoiceParameters,
+};
 use serde::{Deserialize, Serialize};
 
-use crate::cpal_invoke::BEATRICE;
+use crate::audio_engine::{PITCH_TELEMETRY, with_beatrice};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BeatriceModelInfo {
@@ -72,9 +75,7 @@ pub async fn beatrice_get_model_from_path(model_folder: String) -> Option<Beatri
         }
     }
 
-    let Some(toml_path) = toml_path else {
-        return None;
-    };
+    let toml_path = toml_path?;
 
     let beatrice_toml = BeatriceToml::load_from_tomlpath(toml_path).ok()?;
 
@@ -106,30 +107,64 @@ pub async fn beatrice_get_model_from_path(model_folder: String) -> Option<Beatri
 
 #[tauri::command]
 pub async fn beatrice_get_nspeaker() -> Option<i32> {
-    let beatrice = BEATRICE.lock().unwrap();
-    let beatrice = beatrice.as_ref()?;
-
-    beatrice.get_n_speaker()
+    with_beatrice(|beatrice| beatrice.get_n_speaker()).ok()?
 }
 
 #[tauri::command]
 pub async fn beatrice_set_target_speaker(target: i32) -> Result<(), String> {
-    let mut beatrice = BEATRICE.lock().unwrap();
-
-    let Some(beatrice) = beatrice.as_mut() else {
-        return Err(BeatriceError::ModelNotLoaded.to_string());
-    };
+    with_beatrice(move |beatrice| beatrice.set_target_speaker(target as u32))
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
 
-    beatrice
-        .set_target_speaker(target as u32)
+#[tauri::command]
+pub async fn beatrice_set_speaker_mix(speaker_mix: Vec<SpeakerWeight>) -> Result<(), String> {
+    with_beatrice(move |beatrice| beatrice.set_speaker_mix(&speaker_mix))
+        .map_err(|err| err.to_string())?
         .map_err(|err| err.to_string())
 }
 
 #[tauri::command]
 pub async fn beatrice_get_version() -> Option<String> {
-    let mut beatrice = BEATRICE.lock().unwrap();
+    with_beatrice(|beatrice| beatrice.get_model_version().to_string()).ok()
+}
+
+#[tauri::command]
+pub async fn beatrice_get_latency() -> Option<Latency> {
+    with_beatrice(|beatrice| beatrice.get_latency()).ok()
+}
+
+#[tauri::command]
+pub async fn beatrice_get_parameters() -> Option<VoiceParameters> {
+    with_beatrice(|beatrice| beatrice.get_parameters()).ok()
+}
+
+#[tauri::command]
+pub async fn beatrice_get_capabilities() -> Option<Capabilities> {
+    with_beatrice(|beatrice| beatrice.get_capabilities()).ok()
+}
+
+#[tauri::command]
+pub async fn beatrice_reset() -> Result<(), String> {
+    with_beatrice(|beatrice| beatrice.reset()).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn beatrice_get_pitch_calibration() -> Option<CalibrationState> {
+    with_beatrice(|beatrice| beatrice.get_pitch_calibration()).ok()
+}
+
+#[tauri::command]
+pub async fn beatrice_finish_pitch_calibration() -> Option<PitchCalibration> {
+    with_beatrice(|beatrice| beatrice.finish_pitch_calibration()).ok()?
+}
+
+/// 有効にすると hop ごとのピッチの情報を "pitch-telemetry" イベントで送る
+#[tauri::command]
+pub async fn beatrice_set_pitch_telemetry(enabled: bool) {
+    PITCH_TELEMETRY.store(enabled, Ordering::Relaxed);
 
-    Some(beatrice.as_mut()?.get_model_version().to_string())
+    let _ = with_beatrice(move |beatrice| beatrice.set_pitch_telemetry(enabled));
 }
 
 macro_rules! beatrice_command {
@@ -140,14 +175,9 @@ macro_rules! beatrice_command {
     ) => {
         #[tauri::command]
         pub async fn $fn_name($arg: $ty) -> Result<(), String> {
-            let mut beatrice = BEATRICE.lock().unwrap();
-
-            let Some(beatrice) = beatrice.as_mut() else {
-                return Err(BeatriceError::ModelNotLoaded.to_string());
-            };
-
-            beatrice.$method($arg);
-            Ok(())
+            with_beatrice(move |beatrice| beatrice.$method($arg))
+                .map_err(|err| err.to_string())?
+                .map_err(|err| err.to_string())
         }
     };
 }
@@ -158,6 +188,12 @@ beatrice_command!(
     pitch: f64
 );
 
+beatrice_command!(
+    beatrice_set_auto_pitch_shift,
+    set_auto_pitch_shift,
+    auto_pitch_shift: bool
+);
+
 beatrice_command!(
     beatrice_set_formant_shift,
     set_formant_shift,
@@ -176,6 +212,36 @@ beatrice_command!(
     intonation_intensity: f64
 );
 
+beatrice_command!(
+    beatrice_set_pitch_correction,
+    set_pitch_correction,
+    pitch_correction: f64
+);
+
+beatrice_command!(
+    beatrice_set_pitch_correction_mode,
+    set_pitch_correction_mode,
+    pitch_correction_mode: PitchCorrectionMode
+);
+
+beatrice_command!(
+    beatrice_set_pitch_glide_time,
+    set_pitch_glide_time,
+    pitch_glide_time: f64
+);
+
+beatrice_command!(
+    beatrice_set_pitch_median_window,
+    set_pitch_median_window,
+    pitch_median_window: u32
+);
+
+beatrice_command!(
+    beatrice_set_pitch_hysteresis,
+    set_pitch_hysteresis,
+    pitch_hysteresis: f64
+);
+
 beatrice_command!(
     beatrice_set_min_source_pitch,
     set_min_source_pitch,
@@ -193,3 +259,9 @@ beatrice_command!(
     set_vq_num_neighbors,
     vq_num_neighbors: i32
 );
+
+beatrice_command!(
+    beatrice_start_pitch_calibration,
+    start_pitch_calibration,
+    seconds: f64
+);
@@ -1,335 +1,130 @@
-use anyhow::Context;
-use beatrice_lib::Beatrice;
-use cpal::{
-    StreamConfig,
-    traits::{DeviceTrait, HostTrait, StreamTrait as _},
+use tauri::{AppHandle, Manager as _, State};
+
+use crate::{
+    audio_device::{self, AudioDevice, DeviceDirection, StreamSettings, SupportedConfig},
+    audio_engine::{
+        AudioEngine, AudioEngineConfig, AudioEngineStatus, INPUT_GAIN, INPUT_THRESHOLD,
+        MONITOR_GAIN, OUTPUT_GAIN,
+    },
+    audio_host,
 };
-use ringbuf::{
-    HeapRb,
-    traits::{Consumer as _, Producer as _, Split as _},
-};
-use std::{
-    sync::{LazyLock, Mutex, mpsc},
-    thread,
-    time::Duration,
-};
-use tauri::Emitter as _;
 
-pub static BEATRICE: LazyLock<Mutex<Option<Box<dyn Beatrice>>>> =
-    LazyLock::new(|| Mutex::new(None));
+#[tauri::command]
+pub async fn cpal_get_hosts() -> Vec<String> {
+    audio_host::available_host_names()
+}
 
 #[tauri::command]
-pub async fn cpal_get_inputs() -> Result<Vec<String>, String> {
-    let host = cpal::host_from_id(cpal::HostId::Wasapi).map_err(|err| err.to_string())?;
+pub async fn cpal_get_default_host() -> String {
+    audio_host::default_host_name()
+}
 
-    let inputs = host.input_devices().map_err(|e| e.to_string())?;
+#[tauri::command]
+pub async fn cpal_get_inputs(host_name: Option<String>) -> Result<Vec<AudioDevice>, String> {
+    let host = audio_host::open_host(host_name.as_deref()).map_err(|err| err.to_string())?;
 
-    let mut names = Vec::new();
-    for device in inputs {
-        let desc = device.name().map_err(|e| e.to_string())?;
-        names.push(desc);
-    }
+    let inputs =
+        audio_device::list_devices(&host, DeviceDirection::Input).map_err(|e| e.to_string())?;
 
-    Ok(names)
+    Ok(inputs.into_iter().map(|(info, _)| info).collect())
 }
 
 #[tauri::command]
-pub async fn cpal_get_outputs() -> Result<Vec<String>, String> {
-    let host = cpal::default_host();
+pub async fn cpal_get_outputs(host_name: Option<String>) -> Result<Vec<AudioDevice>, String> {
+    let host = audio_host::open_host(host_name.as_deref()).map_err(|err| err.to_string())?;
+
+    let outputs =
+        audio_device::list_devices(&host, DeviceDirection::Output).map_err(|e| e.to_string())?;
 
-    let outputs = host.output_devices().map_err(|e| e.to_string())?;
+    Ok(outputs.into_iter().map(|(info, _)| info).collect())
+}
+
+#[tauri::command]
+pub async fn cpal_get_supported_configs(
+    host_name: Option<String>,
+    device_id: String,
+    direction: DeviceDirection,
+) -> Result<Vec<SupportedConfig>, String> {
+    let host = audio_host::open_host(host_name.as_deref()).map_err(|err| err.to_string())?;
 
-    let mut names = Vec::new();
-    for device in outputs {
-        let desc = device.name().map_err(|e| e.to_string())?;
-        names.push(desc);
-    }
+    let device =
+        audio_device::find_device(&host, direction, &device_id).map_err(|e| e.to_string())?;
 
-    Ok(names)
+    audio_device::supported_configs(&device, direction).map_err(|e| e.to_string())
 }
 
-static INPUT_GAIN: Mutex<f32> = Mutex::new(1.0);
 #[tauri::command]
 pub async fn cpal_set_input_gain(gain: f32) {
-    let mut lock = INPUT_GAIN.lock().unwrap();
-    *lock = gain
+    INPUT_GAIN.store(gain);
 }
 
-static OUTPUT_GAIN: Mutex<f32> = Mutex::new(1.0);
 #[tauri::command]
 pub async fn cpal_set_output_gain(gain: f32) {
-    let mut lock = OUTPUT_GAIN.lock().unwrap();
-    *lock = gain
+    OUTPUT_GAIN.store(gain);
 }
 
-static MONITOR_GAIN: Mutex<f32> = Mutex::new(1.0);
 #[tauri::command]
 pub async fn cpal_set_monitor_gain(gain: f32) {
-    let mut lock = MONITOR_GAIN.lock().unwrap();
-    *lock = gain
+    MONITOR_GAIN.store(gain);
 }
 
-static INPUT_THRESHOLD: Mutex<f32> = Mutex::new(1.0);
-static MIC_LEVEL: Mutex<f32> = Mutex::new(1.0);
 #[tauri::command]
 pub async fn cpal_set_input_threshold(threshold: f32) {
-    let mut lock = INPUT_THRESHOLD.lock().unwrap();
-    *lock = threshold
+    INPUT_THRESHOLD.store(threshold);
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn cpal_start_voice_changer(
-    app_handle: tauri::AppHandle,
+    app_handle: AppHandle,
     model_path: String,
-    input_device_name: Option<String>,
-    output_device_name: Option<String>,
-    monitor_device_name: Option<String>,
-) {
-    thread::spawn(move || {
-        static IS_EXEC: Mutex<bool> = Mutex::new(false);
-        let is_exec = {
-            let mut lock = IS_EXEC.lock().unwrap();
-            let value = *lock;
-            *lock = true;
-
-            value
-        };
-
-        if is_exec {
-            return;
-        }
-
-        loop {
-            let mic_level = {
-                let lock = MIC_LEVEL.lock().unwrap();
-                *lock
-            };
-
-            let _ = app_handle.emit("mic-level", mic_level);
-            thread::sleep(Duration::from_millis(50));
-        }
-    });
-
-    static AUDIO_STOP_SENDER: LazyLock<Mutex<Option<mpsc::Sender<()>>>> =
-        LazyLock::new(|| Mutex::new(None));
-
-    {
-        let mut audio_stop_sender = AUDIO_STOP_SENDER.lock().unwrap();
-        if let Some(sender) = audio_stop_sender.as_ref() {
-            let _ = sender.send(());
-        }
-        *audio_stop_sender = None;
-    }
-
-    let (sender, receiver) = mpsc::channel();
-    {
-        let mut audio_stop_sender = AUDIO_STOP_SENDER.lock().unwrap();
-        *audio_stop_sender = Some(sender);
-    }
-
-    thread::spawn(move || -> anyhow::Result<()> {
-        let (Some(input_device_name), Some(output_device_name)) =
-            (input_device_name, output_device_name)
-        else {
-            return Ok(());
-        };
-
-        let ring_size = 4096;
-
-        let (mut output_producer, mut output_consumer) = HeapRb::new(ring_size).split();
-        let (mut monitor_producer, mut monitor_consumer) = HeapRb::new(ring_size).split();
-
-        let host = cpal::host_from_id(cpal::HostId::Wasapi)?;
-
-        // input
-        let input_devices = host.input_devices()?.collect::<Vec<_>>();
-        let Some(input_idx) = input_devices.iter().position(|device| {
-            device.name().unwrap_or_else(|_| String::new()) == input_device_name
-        }) else {
-            return Ok(());
-        };
-
-        let input_device = host
-            .input_devices()?
-            .nth(input_idx)
-            .context("input_device not found")?;
-        let input_config = input_device.default_input_config()?;
-
-        // output
-        let output_devices = host.output_devices()?.collect::<Vec<_>>();
-        let Some(output_idx) = output_devices.iter().position(|device| {
-            device.name().unwrap_or_else(|_| String::new()) == output_device_name
-        }) else {
-            return Ok(());
-        };
-
-        let output_device = host
-            .output_devices()?
-            .nth(output_idx)
-            .context("output not found")?;
-        let output_config = output_device.default_output_config()?;
-
-        // monitor
-        let monitor_devices = host.output_devices()?.collect::<Vec<_>>();
-        let monitor_device = match monitor_device_name {
-            Some(device_name) if device_name == "None" => None,
-            Some(device_name) => {
-                let Some(monitor_idx) = monitor_devices.iter().position(|device| {
-                    device.name().unwrap_or_else(|_| String::new()) == device_name
-                }) else {
-                    return Ok(());
-                };
-
-                let monitor_device = host
-                    .output_devices()?
-                    .nth(monitor_idx)
-                    .context("output not found")?;
-
-                Some(monitor_device)
-            }
-            None => None,
-        };
-
-        let beatrice = beatrice_lib::new(
-            model_path,
-            input_config.sample_rate().0.into(),
-            output_config.sample_rate().0.into(),
-            input_config.channels().into(),
-            output_config.channels().into(),
-        )?;
-
-        {
-            let mut lock = BEATRICE.lock().unwrap();
-            *lock = Some(beatrice)
-        }
-
-        let input_stream = {
-            let input_stream_config = StreamConfig {
-                channels: input_config.channels(),
-                sample_rate: input_config.sample_rate(),
-                buffer_size: cpal::BufferSize::Fixed(480),
-            };
-
-            input_device.build_input_stream(
-                &input_stream_config,
-                move |data: &[f32], _: &_| {
-                    let mut input_buffer = vec![0.0_f32; data.len()];
-                    input_buffer.copy_from_slice(data);
-
-                    let input_gain = { *INPUT_GAIN.lock().unwrap() };
-                    for i in input_buffer.iter_mut() {
-                        *i *= input_gain;
-                    }
-
-                    let sum_squares: f32 = input_buffer.iter().map(|v| v * v).sum();
-                    let rms = (sum_squares / input_buffer.len() as f32).sqrt();
-                    {
-                        let mut lock = MIC_LEVEL.lock().unwrap();
-                        *lock = rms.powf(0.3);
-                    }
-
-                    let result = {
-                        let input_threshold = {
-                            let lock = INPUT_THRESHOLD.lock().unwrap();
-                            *lock
-                        };
-                        let mic_level = {
-                            let lock = MIC_LEVEL.lock().unwrap();
-                            *lock
-                        };
-
-                        match input_threshold < mic_level {
-                            true => {
-                                let mut beatrice = BEATRICE.lock().unwrap();
-
-                                match beatrice.as_mut() {
-                                    Some(beatrice) => beatrice
-                                        .infer(&input_buffer)
-                                        .unwrap_or_else(|_| vec![0.0; data.len()]),
-
-                                    None => vec![0.0; data.len()],
-                                }
-                            }
-                            false => vec![0.0; data.len()],
-                        }
-                    };
-
-                    output_producer.push_slice(&result);
-                    monitor_producer.push_slice(&result);
-                },
-                |err| eprintln!("入力エラー: {err}"),
-                None,
-            )?
-        };
-
-        let output_stream = {
-            let output_stream_config = StreamConfig {
-                channels: output_config.channels(),
-                sample_rate: output_config.sample_rate(),
-                buffer_size: cpal::BufferSize::Fixed(480),
-            };
-
-            output_device.build_output_stream(
-                &output_stream_config,
-                move |data: &mut [f32], _: &_| {
-                    let mut output_buffer = vec![0.0_f32; data.len()];
-                    output_consumer.pop_slice(&mut output_buffer);
-
-                    let output_gain = { *OUTPUT_GAIN.lock().unwrap() };
-                    for i in output_buffer.iter_mut() {
-                        *i *= output_gain;
-                    }
-
-                    data.copy_from_slice(&output_buffer);
-                },
-                |err| eprintln!("出力エラー: {err}"),
-                None,
-            )?
-        };
-
-        let monitor_stream = match monitor_device {
-            Some(device) => {
-                let monitor_stream_config = StreamConfig {
-                    channels: output_config.channels(),
-                    sample_rate: output_config.sample_rate(),
-                    buffer_size: cpal::BufferSize::Fixed(480),
-                };
-
-                Some(device.build_output_stream(
-                    &monitor_stream_config,
-                    move |data: &mut [f32], _: &_| {
-                        let mut output_buffer = vec![0.0_f32; data.len()];
-                        monitor_consumer.pop_slice(&mut output_buffer);
-
-                        let output_gain = { *MONITOR_GAIN.lock().unwrap() };
-                        for i in output_buffer.iter_mut() {
-                            *i *= output_gain;
-                        }
-
-                        data.copy_from_slice(&output_buffer);
-                    },
-                    |err| eprintln!("出力エラー: {err}"),
-                    None,
-                )?)
-            }
-            None => None,
-        };
-
-        input_stream.play()?;
-        output_stream.play()?;
-        if let Some(stream) = &monitor_stream {
-            stream.play()?;
-        }
+    host_name: Option<String>,
+    input_device_id: Option<String>,
+    output_device_id: Option<String>,
+    monitor_device_id: Option<String>,
+    input_stream: Option<StreamSettings>,
+    output_stream: Option<StreamSettings>,
+    input_channel: Option<u16>,
+) -> Result<(), String> {
+    let config = AudioEngineConfig {
+        model_path,
+        host_name,
+        input_device_id,
+        output_device_id,
+        monitor_device_id,
+        input_stream: input_stream.unwrap_or_default(),
+        output_stream: output_stream.unwrap_or_default(),
+        input_channel,
+    };
+
+    tauri::async_runtime::spawn_blocking(move || {
+        app_handle.state::<AudioEngine>().start(&app_handle, config)
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
 
-        while receiver.recv().is_ok() {
-            input_stream.pause()?;
-            output_stream.pause()?;
+#[tauri::command]
+pub async fn cpal_stop_voice_changer(app_handle: AppHandle) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || app_handle.state::<AudioEngine>().stop())
+        .await
+        .map_err(|err| err.to_string())
+}
 
-            if let Some(stream) = &monitor_stream {
-                stream.pause()?;
-            }
-        }
+#[tauri::command]
+pub async fn cpal_restart_voice_changer(app_handle: AppHandle) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        app_handle.state::<AudioEngine>().restart(&app_handle)
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
 
-        Ok(())
-    });
+#[tauri::command]
+pub async fn cpal_get_voice_changer_status(
+    engine: State<'_, AudioEngine>,
+) -> Result<AudioEngineStatus, String> {
+    Ok(engine.status())
 }
@@ -1,3 +1,6 @@
+mod audio_device;
+mod audio_engine;
+mod audio_host;
 mod beatrice_invoke;
 mod cpal_invoke;
 
@@ -7,25 +10,51 @@ pub fn run() {
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
+        .manage(audio_engine::AudioEngine::default())
+        .setup(|app| {
+            audio_device::spawn_device_watcher(app.handle().clone());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
+            cpal_invoke::cpal_get_hosts,
+            cpal_invoke::cpal_get_default_host,
             cpal_invoke::cpal_get_inputs,
             cpal_invoke::cpal_get_outputs,
+            cpal_invoke::cpal_get_supported_configs,
             cpal_invoke::cpal_set_input_gain,
             cpal_invoke::cpal_set_output_gain,
             cpal_invoke::cpal_set_monitor_gain,
             cpal_invoke::cpal_start_voice_changer,
+            cpal_invoke::cpal_stop_voice_changer,
+            cpal_invoke::cpal_restart_voice_changer,
+            cpal_invoke::cpal_get_voice_changer_status,
             cpal_invoke::cpal_set_input_threshold,
             beatrice_invoke::beatrice_get_model_from_path,
             beatrice_invoke::beatrice_get_nspeaker,
             beatrice_invoke::beatrice_set_target_speaker,
+            beatrice_invoke::beatrice_set_speaker_mix,
             beatrice_invoke::beatrice_get_version,
+            beatrice_invoke::beatrice_get_latency,
+            beatrice_invoke::beatrice_get_parameters,
+            beatrice_invoke::beatrice_get_capabilities,
+            beatrice_invoke::beatrice_reset,
+            beatrice_invoke::beatrice_start_pitch_calibration,
+            beatrice_invoke::beatrice_get_pitch_calibration,
+            beatrice_invoke::beatrice_finish_pitch_calibration,
+            beatrice_invoke::beatrice_set_pitch_telemetry,
             beatrice_invoke::beatrice_set_pitch,
+            beatrice_invoke::beatrice_set_auto_pitch_shift,
             beatrice_invoke::beatrice_set_formant_shift,
             beatrice_invoke::beatrice_set_average_source_pitch,
             beatrice_invoke::beatrice_set_min_source_pitch,
             beatrice_invoke::beatrice_set_max_source_pitch,
             beatrice_invoke::beatrice_set_vq_num_neighbors,
             beatrice_invoke::beatrice_set_intonation_intensity,
+            beatrice_invoke::beatrice_set_pitch_correction,
+            beatrice_invoke::beatrice_set_pitch_correction_mode,
+            beatrice_invoke::beatrice_set_pitch_glide_time,
+            beatrice_invoke::beatrice_set_pitch_median_window,
+            beatrice_invoke::beatrice_set_pitch_hysteresis,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -3,8 +3,15 @@ import "./App.css";
 import { SelectModel } from "./components/mycomponent/modelSelect";
 import { VoiceSettings } from "./components/mycomponent/voiceSettings";
 import { useAtom } from "jotai";
-import { jotaiAtoms } from "./jotaiAtoms";
-import { rustInvoke } from "./rustInvoke";
+import { jotaiAtoms, VoiceSetting } from "./jotaiAtoms";
+import {
+  AudioDevice,
+  AudioDevicesChanged,
+  BeatriceCapabilities,
+  rustInvoke,
+  StreamSettings,
+} from "./rustInvoke";
+import * as tauriEvent from "@tauri-apps/api/event";
 import * as tauriStore from "@tauri-apps/plugin-store";
 import { TauriStoreInterface, tauriStoreKey } from "./tauriStore";
 
@@ -39,16 +46,50 @@ function App() {
 export default App;
 
 function InitGrobal() {
-  // 使用できる入力・出力デバイスを取得
+  // 使用できるホストを取得
+  const [, setHosts] = useAtom(jotaiAtoms.hosts);
+  useEffect(() => {
+    const promise = async () => {
+      setHosts(await rustInvoke.cpal.getHosts());
+    };
+    promise();
+  }, []);
+
+  // 選択中のホストで使用できる入力・出力デバイスを取得
+  const [deviceSetting] = useAtom(jotaiAtoms.deviceSetting);
   const [, setInputDevices] = useAtom(jotaiAtoms.inputDevices);
   const [, setOutputDevices] = useAtom(jotaiAtoms.outputDevices);
   useEffect(() => {
+    let unlisten: (() => void) | null = null;
+
+    const updateDevices = async () => {
+      try {
+        setInputDevices(await rustInvoke.cpal.getInputs(deviceSetting.host));
+        setOutputDevices(await rustInvoke.cpal.getOutputs(deviceSetting.host));
+      } catch (err) {
+        console.error(err);
+        setInputDevices([]);
+        setOutputDevices([]);
+      }
+    };
+
+    // デバイスが抜き差しされたら取得し直す
     const promise = async () => {
-      setInputDevices(await rustInvoke.cpal.getInputs());
-      setOutputDevices(await rustInvoke.cpal.getOutputs());
+      await updateDevices();
+
+      unlisten = await tauriEvent.listen<AudioDevicesChanged>(
+        "audio-devices-changed",
+        () => updateDevices(),
+      );
     };
     promise();
-  }, []);
+
+    return () => {
+      if (unlisten !== null) {
+        unlisten();
+      }
+    };
+  }, [deviceSetting.host]);
 
   return <></>;
 }
@@ -67,9 +108,13 @@ function SaveTauriStore({ isLoadStore }: { isLoadStore: boolean }) {
         const storeValue: TauriStoreInterface = {
           modelFolderPaths: loadedModels.map((i) => i.model_path),
 
+          audioHost: deviceSetting.host,
           inputDevice: deviceSetting.input,
           outputDevice: deviceSetting.output,
           monitorDevice: deviceSetting.monitor,
+          sampleRate: deviceSetting.sampleRate,
+          bufferSize: deviceSetting.bufferSize,
+          inputChannel: deviceSetting.inputChannel,
 
           pitch: voiceSetting.pitch,
           formantShift: voiceSetting.formant,
@@ -94,6 +139,19 @@ function SaveTauriStore({ isLoadStore }: { isLoadStore: boolean }) {
   return <></>;
 }
 
+// 以前はデバイス名で保存していたので、ID に見つからなければ同じ名前の最初のデバイスの ID にする
+// どちらにも見つからない (今は外れている) ときは保存していた値のまま
+function migrateDeviceId(
+  value: string | null,
+  devices: AudioDevice[],
+): string | null {
+  if (value === null || devices.some((d) => d.id === value)) {
+    return value;
+  }
+
+  return devices.find((d) => d.name === value)?.id ?? value;
+}
+
 function LoadTauriStore({
   setIsLoadStore,
 }: {
@@ -134,10 +192,32 @@ function LoadTauriStore({
         inputThreshold: storeValue?.inputThreshold ?? 0.0,
       });
 
+      const host =
+        storeValue?.audioHost ?? (await rustInvoke.cpal.getDefaultHost());
+
+      let inputDevices: AudioDevice[] = [];
+      let outputDevices: AudioDevice[] = [];
+      try {
+        inputDevices = await rustInvoke.cpal.getInputs(host);
+        outputDevices = await rustInvoke.cpal.getOutputs(host);
+      } catch (err) {
+        console.error(err);
+      }
+
       setDeviceSetting({
-        input: storeValue?.inputDevice ?? null,
-        output: storeValue?.outputDevice ?? null,
-        monitor: storeValue?.monitorDevice ?? null,
+        host: host,
+        input: migrateDeviceId(storeValue?.inputDevice ?? null, inputDevices),
+        output: migrateDeviceId(
+          storeValue?.outputDevice ?? null,
+          outputDevices,
+        ),
+        monitor: migrateDeviceId(
+          storeValue?.monitorDevice ?? null,
+          outputDevices,
+        ),
+        sampleRate: storeValue?.sampleRate ?? null,
+        bufferSize: storeValue?.bufferSize ?? null,
+        inputChannel: storeValue?.inputChannel ?? null,
       });
 
       setIsLoadStore(true);
@@ -148,6 +228,50 @@ function LoadTauriStore({
   return <></>;
 }
 
+// 声の設定を Beatrice に送る。モデルが対応していない設定は送らず、失敗はログに出すだけにする
+function sendVoiceSetting(
+  voiceSetting: VoiceSetting,
+  capabilities: BeatriceCapabilities | null,
+) {
+  const requests = [
+    rustInvoke.beatrice.setPitch(voiceSetting.pitch),
+    rustInvoke.beatrice.setFormantShift(voiceSetting.formant),
+    rustInvoke.beatrice.setIntonationIntensity(
+      voiceSetting.intonationIntensity,
+    ),
+  ];
+
+  if (capabilities?.min_source_pitch) {
+    requests.push(
+      rustInvoke.beatrice.setMinSourcePitch(voiceSetting.minSourcePitch),
+    );
+  }
+  if (capabilities?.max_source_pitch) {
+    requests.push(
+      rustInvoke.beatrice.setMaxSourcePitch(voiceSetting.maxSourcePitch),
+    );
+  }
+  if (capabilities?.vq_num_neighbors) {
+    requests.push(
+      rustInvoke.beatrice.setVqNumNeighbors(voiceSetting.vqNeighborCount),
+    );
+  }
+
+  for (const request of requests) {
+    request.catch((err) => console.error(err));
+  }
+}
+
+// ボイスチェンジャーを起動し直したあとに、動いているモデルが対応している設定を取り直す
+async function fetchCapabilities() {
+  try {
+    return await rustInvoke.beatrice.getCapabilities();
+  } catch (err) {
+    console.error(err);
+    return null;
+  }
+}
+
 // Beatrice側を更新する
 function SyncBeatrice() {
   const [selectModel] = useAtom(jotaiAtoms.selectModel);
@@ -157,30 +281,45 @@ function SyncBeatrice() {
   const [deviceSetting] = useAtom(jotaiAtoms.deviceSetting);
   const [voiceSetting] = useAtom(jotaiAtoms.voiceSetting);
   const [outputSetting] = useAtom(jotaiAtoms.outputSetting);
+  const [capabilities, setCapabilities] = useAtom(jotaiAtoms.capabilities);
 
   // モデル
   useEffect(() => {
     const promise = async () => {
       if (selectModel !== null) {
-        await rustInvoke.cpal.startVoiceChanger(
-          selectModel.model_path,
-          deviceSetting.input,
-          deviceSetting.output,
-          deviceSetting.monitor,
-        );
+        const streamSettings: StreamSettings = {
+          sample_rate: deviceSetting.sampleRate,
+          channels: null,
+          buffer_size: deviceSetting.bufferSize,
+        };
+
+        try {
+          await rustInvoke.cpal.startVoiceChanger(
+            selectModel.model_path,
+            deviceSetting.host,
+            deviceSetting.input,
+            deviceSetting.output,
+            deviceSetting.monitor,
+            streamSettings,
+            streamSettings,
+            deviceSetting.inputChannel,
+          );
+        } catch (err) {
+          console.error(err);
+          setCapabilities(null);
+          return;
+        }
 
         await new Promise((resolve) => setTimeout(resolve, 100));
 
+        const capabilities = await fetchCapabilities();
+        setCapabilities(capabilities);
+
         setSelectSpeakerIdx(0);
-        rustInvoke.beatrice.setTargetSpeaker(0);
-        rustInvoke.beatrice.setPitch(voiceSetting.pitch);
-        rustInvoke.beatrice.setFormantShift(voiceSetting.formant);
-        rustInvoke.beatrice.setIntonationIntensity(
-          voiceSetting.intonationIntensity,
-        );
-        rustInvoke.beatrice.setMinSourcePitch(voiceSetting.minSourcePitch);
-        rustInvoke.beatrice.setMaxSourcePitch(voiceSetting.maxSourcePitch);
-        rustInvoke.beatrice.setVqNumNeighbors(voiceSetting.vqNeighborCount);
+        rustInvoke.beatrice
+          .setTargetSpeaker(0)
+          .catch((err) => console.error(err));
+        sendVoiceSetting(voiceSetting, capabilities);
       }
     };
 
@@ -191,23 +330,35 @@ function SyncBeatrice() {
   useEffect(() => {
     const promise = async () => {
       if (selectModel !== null) {
-        await rustInvoke.cpal.startVoiceChanger(
-          selectModel.model_path,
-          deviceSetting.input,
-          deviceSetting.output,
-          deviceSetting.monitor,
-        );
+        const streamSettings: StreamSettings = {
+          sample_rate: deviceSetting.sampleRate,
+          channels: null,
+          buffer_size: deviceSetting.bufferSize,
+        };
+
+        try {
+          await rustInvoke.cpal.startVoiceChanger(
+            selectModel.model_path,
+            deviceSetting.host,
+            deviceSetting.input,
+            deviceSetting.output,
+            deviceSetting.monitor,
+            streamSettings,
+            streamSettings,
+            deviceSetting.inputChannel,
+          );
+        } catch (err) {
+          console.error(err);
+          setCapabilities(null);
+          return;
+        }
 
         await new Promise((resolve) => setTimeout(resolve, 100));
 
-        rustInvoke.beatrice.setPitch(voiceSetting.pitch);
-        rustInvoke.beatrice.setFormantShift(voiceSetting.formant);
-        rustInvoke.beatrice.setIntonationIntensity(
-          voiceSetting.intonationIntensity,
-        );
-        rustInvoke.beatrice.setMinSourcePitch(voiceSetting.minSourcePitch);
-        rustInvoke.beatrice.setMaxSourcePitch(voiceSetting.maxSourcePitch);
-        rustInvoke.beatrice.setVqNumNeighbors(voiceSetting.vqNeighborCount);
+        const capabilities = await fetchCapabilities();
+        setCapabilities(capabilities);
+
+        sendVoiceSetting(voiceSetting, capabilities);
       }
     };
 
@@ -221,31 +372,19 @@ function SyncBeatrice() {
     rustInvoke.cpal.setMonitorGain(outputSetting.monitorGain);
     rustInvoke.cpal.setInputThreshold(outputSetting.inputThreshold);
 
-    rustInvoke.beatrice.setPitch(voiceSetting.pitch);
-    rustInvoke.beatrice.setFormantShift(voiceSetting.formant);
-    rustInvoke.beatrice.setIntonationIntensity(
-      voiceSetting.intonationIntensity,
-    );
-    rustInvoke.beatrice.setMinSourcePitch(voiceSetting.minSourcePitch);
-    rustInvoke.beatrice.setMaxSourcePitch(voiceSetting.maxSourcePitch);
-    rustInvoke.beatrice.setVqNumNeighbors(voiceSetting.vqNeighborCount);
+    sendVoiceSetting(voiceSetting, capabilities);
   }, [outputSetting]);
 
   // ピッチなど
   useEffect(() => {
-    rustInvoke.beatrice.setPitch(voiceSetting.pitch);
-    rustInvoke.beatrice.setFormantShift(voiceSetting.formant);
-    rustInvoke.beatrice.setIntonationIntensity(
-      voiceSetting.intonationIntensity,
-    );
-    rustInvoke.beatrice.setMinSourcePitch(voiceSetting.minSourcePitch);
-    rustInvoke.beatrice.setMaxSourcePitch(voiceSetting.maxSourcePitch);
-    rustInvoke.beatrice.setVqNumNeighbors(voiceSetting.vqNeighborCount);
+    sendVoiceSetting(voiceSetting, capabilities);
   }, [voiceSetting]);
 
   // スピーカー変更時
   useEffect(() => {
-    rustInvoke.beatrice.setTargetSpeaker(selectSpeakerIdx);
+    rustInvoke.beatrice
+      .setTargetSpeaker(selectSpeakerIdx)
+      .catch((err) => console.error(err));
   }, [selectSpeakerIdx]);
 
   return <></>;
@@ -24,6 +24,7 @@ import {
 import { Card } from "@/components/ui/card";
 import { useAtom } from "jotai";
 import { jotaiAtoms } from "@/jotaiAtoms";
+import { AudioDevice, rustInvoke, SupportedConfig } from "@/rustInvoke";
 import * as tauriEvent from "@tauri-apps/api/event";
 import * as tauriCore from "@tauri-apps/api/core";
 
@@ -55,7 +56,7 @@ function SelectDeviceChild({
   placeholder,
 }: {
   label: string;
-  devices: string[];
+  devices: AudioDevice[];
   value: string | null;
   onValueChange: (v: string) => void;
   description: string;
@@ -77,7 +78,47 @@ function SelectDeviceChild({
         </SelectTrigger>
 
         <SelectContent>
-          {["None", ...devices].map((i) => (
+          <SelectItem value="None">None</SelectItem>
+          {devices.map((i) => (
+            <SelectItem value={i.id}>{i.name}</SelectItem>
+          ))}
+        </SelectContent>
+      </Select>
+    </div>
+  );
+}
+
+function SelectHost() {
+  const [hosts] = useAtom(jotaiAtoms.hosts);
+  const [deviceSetting, setDeviceSetting] = useAtom(jotaiAtoms.deviceSetting);
+
+  return (
+    <div className="flex flex-col gap-3 w-[33%]">
+      <div className="flex gap-2">
+        <Label>Audio Host</Label>
+        <QuestionTooltip
+          description="デバイスの取得と入出力に使用するオーディオAPIを選択します。変更するとデバイスの選択はリセットされます。"
+        />
+      </div>
+      <Select
+        value={deviceSetting.host ?? undefined}
+        onValueChange={(v) =>
+          setDeviceSetting((prev) => ({
+            ...prev,
+            host: v,
+            input: null,
+            output: null,
+            monitor: null,
+            inputChannel: null,
+          }))
+        }
+      >
+        <SelectTrigger className="w-[90%] max-w-[90%]">
+          <SelectValue placeholder="Select Host" />
+        </SelectTrigger>
+
+        <SelectContent>
+          {hosts.map((i) => (
             <SelectItem value={i}>{i}</SelectItem>
           ))}
         </SelectContent>
@@ -101,6 +142,7 @@ function SelectDevice() {
           setDeviceSetting((prev) => ({
             ...prev,
             input: v,
+            inputChannel: null,
           }))
         }
         description="ボイスチェンジャーに使用するマイクを選択します。"
@@ -136,6 +178,147 @@ function SelectDevice() {
   );
 }
 
+const sampleRates = [44100, 48000, 88200, 96000, 192000];
+const bufferSizes = [64, 128, 256, 480, 512, 1024, 2048];
+
+function supportsSampleRate(configs: SupportedConfig[], rate: number) {
+  return configs.some(
+    (c) => c.min_sample_rate <= rate && rate <= c.max_sample_rate,
+  );
+}
+
+function supportsBufferSize(configs: SupportedConfig[], size: number) {
+  return configs.some(
+    (c) =>
+      (c.min_buffer_size ?? 0) <= size &&
+      size <= (c.max_buffer_size ?? Infinity),
+  );
+}
+
+function SelectStreamSettingChild({
+  label,
+  options,
+  value,
+  onValueChange,
+  description,
+  defaultLabel,
+}: {
+  label: string;
+  options: { value: number; label: string }[];
+  value: number | null;
+  onValueChange: (v: number | null) => void;
+  description: string;
+  defaultLabel: string;
+}) {
+  return (
+    <div className="flex flex-col gap-3 w-[33%]">
+      <div className="flex gap-2">
+        <Label>{label}</Label>
+        <QuestionTooltip description={description} />
+      </div>
+      <Select
+        value={value === null ? "Default" : value.toString()}
+        onValueChange={(v) => onValueChange(v === "Default" ? null : Number(v))}
+      >
+        <SelectTrigger className="w-[90%] max-w-[90%]">
+          <SelectValue />
+        </SelectTrigger>
+
+        <SelectContent>
+          <SelectItem value="Default">{defaultLabel}</SelectItem>
+          {options.map((i) => (
+            <SelectItem value={i.value.toString()}>{i.label}</SelectItem>
+          ))}
+        </SelectContent>
+      </Select>
+    </div>
+  );
+}
+
+function SelectStreamSetting() {
+  const [deviceSetting, setDeviceSetting] = useAtom(jotaiAtoms.deviceSetting);
+  const [inputConfigs, setInputConfigs] = useState<SupportedConfig[]>([]);
+  const [outputConfigs, setOutputConfigs] = useState<SupportedConfig[]>([]);
+
+  // 選択中のデバイスが対応している設定を取得
+  useEffect(() => {
+    const promise = async () => {
+      const getConfigs = async (
+        deviceId: string | null,
+        direction: AudioDevice["direction"],
+      ) => {
+        if (deviceId === null || deviceId === "None") {
+          return [];
+        }
+
+        try {
+          return await rustInvoke.cpal.getSupportedConfigs(
+            deviceSetting.host,
+            deviceId,
+            direction,
+          );
+        } catch (err) {
+          console.error(err);
+          return [];
+        }
+      };
+
+      setInputConfigs(await getConfigs(deviceSetting.input, "Input"));
+      setOutputConfigs(await getConfigs(deviceSetting.output, "Output"));
+    };
+    promise();
+  }, [deviceSetting.host, deviceSetting.input, deviceSetting.output]);
+
+  const configs = [...inputConfigs, ...outputConfigs];
+  const inputChannels = Math.max(0, ...inputConfigs.map((c) => c.channels));
+
+  return (
+    <div className="flex justify-between">
+      <SelectStreamSettingChild
+        label="Sample Rate"
+        options={sampleRates
+          .filter(
+            (r) =>
+              supportsSampleRate(inputConfigs, r) &&
+              supportsSampleRate(outputConfigs, r),
+          )
+          .map((r) => ({ value: r, label: `${r} Hz` }))}
+        value={deviceSetting.sampleRate}
+        onValueChange={(v) =>
+          setDeviceSetting((prev) => ({ ...prev, sampleRate: v }))
+        }
+        description="入力・出力デバイスのサンプリングレートを選択します。"
+        defaultLabel="Device Default"
+      />
+      <SelectStreamSettingChild
+        label="Buffer Size"
+        options={bufferSizes
+          .filter((s) => supportsBufferSize(configs, s))
+          .map((s) => ({ value: s, label: `${s} frames` }))}
+        value={deviceSetting.bufferSize}
+        onValueChange={(v) =>
+          setDeviceSetting((prev) => ({ ...prev, bufferSize: v }))
+        }
+        description="デバイスとやり取りする1回あたりのフレーム数を選択します。小さいほど遅延が減りますが、音が途切れやすくなります。"
+        defaultLabel="Default"
+      />
+      <SelectStreamSettingChild
+        label="Input Channel"
+        options={[...Array(inputChannels).keys()].map((c) => ({
+          value: c,
+          label: `Channel ${c + 1}`,
+        }))}
+        value={deviceSetting.inputChannel}
+        onValueChange={(v) =>
+          setDeviceSetting((prev) => ({ ...prev, inputChannel: v }))
+        }
+        description="マイクが接続されている入力チャンネルを選択します。"
+        defaultLabel="All Channels"
+      />
+    </div>
+  );
+}
+
 function MicLevelMeter() {
   const [level, setLevel] = useState(0);
 
@@ -212,9 +395,7 @@ export function SliderOption({
 
 function VoiceAccordion() {
   const [voiceSetting, setVoiceSetting] = useAtom(jotaiAtoms.voiceSetting);
-  const [selectModel] = useAtom(jotaiAtoms.selectModel);
-
-  const isRcVersion = selectModel?.version === "2.0.0-rc.0";
+  const [capabilities] = useAtom(jotaiAtoms.capabilities);
 
   return (
     <AccordionItem value="voiceSetting">
@@ -259,7 +440,7 @@ function VoiceAccordion() {
           setValue={(v) => setVoiceSetting((prev) => ({ ...prev, formant: v }))}
           min={-2}
           max={2}
-          step={0.5}
+          step={0.01}
         />
         <SliderOption
           label="IntonationIntensity"
@@ -272,7 +453,7 @@ function VoiceAccordion() {
           max={3}
           step={0.1}
         />
-        {isRcVersion ? (
+        {capabilities?.min_source_pitch ? (
           <SliderOption
             label="MinSourcePitch"
             description="認識する声の高さの最低値を調節します。"
@@ -287,7 +468,7 @@ function VoiceAccordion() {
         ) : (
           <></>
         )}
-        {isRcVersion ? (
+        {capabilities?.max_source_pitch ? (
           <SliderOption
             label="MaxSourcePitch"
             description="認識する声の高さの最高値を調節します。"
@@ -302,7 +483,7 @@ function VoiceAccordion() {
         ) : (
           <></>
         )}
-        {isRcVersion ? (
+        {capabilities?.vq_num_neighbors ? (
           <SliderOption
             label="VQNeighborCount"
             description="kNN-VC 的な処理の k の値。有効化すると話者類似性が向上するが、やや滑舌が悪化します。"
@@ -398,7 +579,9 @@ function OutputSettingAccordion() {
         />
         <MicLevelMeter />
         <div className="" />
+        <SelectHost />
         <SelectDevice />
+        <SelectStreamSetting />
       </AccordionContent>
     </AccordionItem>
   );
@@ -1,7 +1,11 @@
 import { atom } from "jotai";
-import { BeatriceModelInfo } from "./rustInvoke";
+import {
+  AudioDevice,
+  BeatriceCapabilities,
+  BeatriceModelInfo,
+} from "./rustInvoke";
 
-interface VoiceSetting {
+export interface VoiceSetting {
   pitch: number;
   formant: number;
   intonationIntensity: number;
@@ -18,18 +22,25 @@ interface OutputSetting {
 }
 
 interface DeviceSetting {
+  host: string | null;
   input: string | null;
   output: string | null;
   monitor: string | null;
+  sampleRate: number | null;
+  bufferSize: number | null;
+  inputChannel: number | null;
 }
 
 export const jotaiAtoms = {
   loadedModels: atom<BeatriceModelInfo[]>([]),
   selectModel: atom<BeatriceModelInfo | null>(null),
   selectSpeakerIdx: atom<number>(0),
+  // 動いているモデルが対応している設定。ボイスチェンジャーが動いていなければ null
+  capabilities: atom<BeatriceCapabilities | null>(null),
 
-  inputDevices: atom<string[]>([]),
-  outputDevices: atom<string[]>([]),
+  hosts: atom<string[]>([]),
+  inputDevices: atom<AudioDevice[]>([]),
+  outputDevices: atom<AudioDevice[]>([]),
 
   voiceSetting: atom<VoiceSetting>({
     pitch: 0.0,
@@ -48,8 +59,12 @@ export const jotaiAtoms = {
   }),
 
   deviceSetting: atom<DeviceSetting>({
+    host: null,
     input: null,
     output: null,
     monitor: null,
+    sampleRate: null,
+    bufferSize: null,
+    inputChannel: null,
   }),
 };
@@ -1,11 +1,33 @@
 import * as tauri from "@tauri-apps/api/core";
 
 const cpal = {
-  getInputs: async () => {
-    return await tauri.invoke<string[]>("cpal_get_inputs");
+  getHosts: async () => {
+    return await tauri.invoke<string[]>("cpal_get_hosts");
   },
-  getOutputs: async () => {
-    return await tauri.invoke<string[]>("cpal_get_outputs");
+  getDefaultHost: async () => {
+    return await tauri.invoke<string>("cpal_get_default_host");
+  },
+  getInputs: async (hostName: string | null) => {
+    return await tauri.invoke<AudioDevice[]>("cpal_get_inputs", {
+      hostName: hostName,
+    });
+  },
+  getOutputs: async (hostName: string | null) => {
+    return await tauri.invoke<AudioDevice[]>("cpal_get_outputs", {
+      hostName: hostName,
+    });
+  },
+
+  getSupportedConfigs: async (
+    hostName: string | null,
+    deviceId: string,
+    direction: AudioDevice["direction"],
+  ) => {
+    return await tauri.invoke<SupportedConfig[]>("cpal_get_supported_configs", {
+      hostName: hostName,
+      deviceId: deviceId,
+      direction: direction,
+    });
   },
 
   setInputGain: async (gain: number) => {
@@ -25,19 +47,70 @@ const cpal = {
 
   startVoiceChanger: async (
     modelPath: string,
-    inputDeviceName: string | null,
-    outputDeviceName: string | null,
-    monitorDeviceName: string | null,
+    hostName: string | null,
+    inputDeviceId: string | null,
+    outputDeviceId: string | null,
+    monitorDeviceId: string | null,
+    inputStream: StreamSettings | null,
+    outputStream: StreamSettings | null,
+    inputChannel: number | null,
   ) => {
     await tauri.invoke<void>("cpal_start_voice_changer", {
       modelPath: modelPath,
-      inputDeviceName: inputDeviceName,
-      outputDeviceName: outputDeviceName,
-      monitorDeviceName: monitorDeviceName,
+      hostName: hostName,
+      inputDeviceId: inputDeviceId,
+      outputDeviceId: outputDeviceId,
+      monitorDeviceId: monitorDeviceId,
+      inputStream: inputStream,
+      outputStream: outputStream,
+      inputChannel: inputChannel,
     });
   },
+  stopVoiceChanger: async () => {
+    await tauri.invoke<void>("cpal_stop_voice_changer");
+  },
+  restartVoiceChanger: async () => {
+    await tauri.invoke<void>("cpal_restart_voice_changer");
+  },
+  getVoiceChangerStatus: async () => {
+    return await tauri.invoke<AudioEngineStatus>(
+      "cpal_get_voice_changer_status",
+    );
+  },
 };
 
+export type AudioEngineStatus =
+  | "Stopped"
+  | "Running"
+  | { Failed: string }
+  | { Disconnected: string };
+
+export interface AudioDevice {
+  id: string;
+  host: string;
+  name: string;
+  direction: "Input" | "Output";
+}
+
+export interface StreamSettings {
+  sample_rate: number | null;
+  channels: number | null;
+  buffer_size: number | null;
+}
+
+export interface SupportedConfig {
+  channels: number;
+  min_sample_rate: number;
+  max_sample_rate: number;
+  min_buffer_size: number | null;
+  max_buffer_size: number | null;
+}
+
+export interface AudioDevicesChanged {
+  added: AudioDevice[];
+  removed: AudioDevice[];
+}
+
 export interface BeatriceVoiceInfo {
   name: string;
   description: string;
@@ -46,6 +119,93 @@ export interface BeatriceVoiceInfo {
   portrait_description: string | null;
 }
 
+export interface BeatriceLatency {
+  input_frames: number;
+  output_frames: number;
+}
+
+export type BeatriceScale =
+  | "Major"
+  | "Minor"
+  | "MajorPentatonic"
+  | "MinorPentatonic"
+  | { Custom: boolean[] };
+
+export interface BeatriceScaleCorrection {
+  key: number;
+  scale: BeatriceScale;
+  reference_frequency: number;
+}
+
+export type BeatricePitchCorrectionMode =
+  | "Off"
+  | "Push"
+  | "Pull"
+  | { Scale: BeatriceScaleCorrection };
+
+export interface BeatriceSpeakerWeight {
+  speaker: number;
+  weight: number;
+}
+
+export interface BeatriceVoiceParameters {
+  target_speaker: number;
+  speaker_mix: BeatriceSpeakerWeight[];
+  pitch_shift: number;
+  auto_pitch_shift: boolean;
+  formant_shift: number;
+  average_source_pitch: number;
+  intonation_intensity: number;
+  pitch_correction: number;
+  pitch_correction_mode: BeatricePitchCorrectionMode;
+  pitch_glide_time: number;
+  pitch_median_window: number;
+  pitch_hysteresis: number;
+  min_source_pitch: number | null;
+  max_source_pitch: number | null;
+  vq_num_neighbors: number | null;
+}
+
+export interface BeatriceParameterRange {
+  min: number;
+  max: number;
+  step: number;
+  default: number;
+}
+
+export interface BeatriceCapabilities {
+  pitch_shift: BeatriceParameterRange;
+  formant_shift: BeatriceParameterRange;
+  average_source_pitch: BeatriceParameterRange;
+  intonation_intensity: BeatriceParameterRange;
+  pitch_correction: BeatriceParameterRange;
+  reference_frequency: BeatriceParameterRange;
+  pitch_glide_time: BeatriceParameterRange;
+  pitch_median_window: BeatriceParameterRange;
+  pitch_hysteresis: BeatriceParameterRange;
+  min_source_pitch: BeatriceParameterRange | null;
+  max_source_pitch: BeatriceParameterRange | null;
+  vq_num_neighbors: BeatriceParameterRange | null;
+}
+
+export interface BeatricePitchCalibration {
+  average_source_pitch: number;
+  min_source_pitch: number | null;
+  max_source_pitch: number | null;
+  voiced_hops: number;
+}
+
+export type BeatriceCalibrationState =
+  | "Idle"
+  | { Running: { progress: number } }
+  | { Finished: BeatricePitchCalibration };
+
+export interface BeatricePitchTelemetry {
+  source_pitch: number | null;
+  output_pitch: number | null;
+  voiced: boolean;
+}
+
 export interface BeatriceModelInfo {
   model_path: string;
   version: string;
@@ -73,14 +233,70 @@ const beatrice = {
     });
   },
 
+  setSpeakerMix: async (speakerMix: BeatriceSpeakerWeight[]) => {
+    return await tauri.invoke<null>("beatrice_set_speaker_mix", {
+      speakerMix: speakerMix,
+    });
+  },
+
   getVersion: async () => {
     return await tauri.invoke<string | null>("beatrice_get_version");
   },
 
+  getLatency: async () => {
+    return await tauri.invoke<BeatriceLatency | null>("beatrice_get_latency");
+  },
+
+  getParameters: async () => {
+    return await tauri.invoke<BeatriceVoiceParameters | null>(
+      "beatrice_get_parameters",
+    );
+  },
+
+  getCapabilities: async () => {
+    return await tauri.invoke<BeatriceCapabilities | null>(
+      "beatrice_get_capabilities",
+    );
+  },
+
+  reset: async () => {
+    await tauri.invoke<null>("beatrice_reset");
+  },
+
+  startPitchCalibration: async (seconds: number) => {
+    await tauri.invoke<null>("beatrice_start_pitch_calibration", {
+      seconds: seconds,
+    });
+  },
+
+  getPitchCalibration: async () => {
+    return await tauri.invoke<BeatriceCalibrationState | null>(
+      "beatrice_get_pitch_calibration",
+    );
+  },
+
+  finishPitchCalibration: async () => {
+    return await tauri.invoke<BeatricePitchCalibration | null>(
+      "beatrice_finish_pitch_calibration",
+    );
+  },
+
+  setPitchTelemetry: async (enabled: boolean) => {
+    await tauri.invoke<void>("beatrice_set_pitch_telemetry", {
+      enabled: enabled,
+    });
+  },
+
   setPitch: async (pitch: number) => {
     await tauri.invoke<null>("beatrice_set_pitch", { pitch: pitch });
   },
 
+  setAutoPitchShift: async (autoPitchShift: boolean) => {
+    await tauri.invoke<null>("beatrice_set_auto_pitch_shift", {
+      autoPitchShift: autoPitchShift,
+    });
+  },
+
   setFormantShift: async (formant: number) => {
     await tauri.invoke<null>("beatrice_set_formant_shift", {
       formant: formant,
@@ -99,6 +315,38 @@ const beatrice = {
     });
   },
 
+  setPitchCorrection: async (pitchCorrection: number) => {
+    await tauri.invoke<null>("beatrice_set_pitch_correction", {
+      pitchCorrection: pitchCorrection,
+    });
+  },
+
+  setPitchCorrectionMode: async (
+    pitchCorrectionMode: BeatricePitchCorrectionMode,
+  ) => {
+    await tauri.invoke<null>("beatrice_set_pitch_correction_mode", {
+      pitchCorrectionMode: pitchCorrectionMode,
+    });
+  },
+
+  setPitchGlideTime: async (pitchGlideTime: number) => {
+    await tauri.invoke<null>("beatrice_set_pitch_glide_time", {
+      pitchGlideTime: pitchGlideTime,
+    });
+  },
+
+  setPitchMedianWindow: async (pitchMedianWindow: number) => {
+    await tauri.invoke<null>("beatrice_set_pitch_median_window", {
+      pitchMedianWindow: pitchMedianWindow,
+    });
+  },
+
+  setPitchHysteresis: async (pitchHysteresis: number) => {
+    await tauri.invoke<null>("beatrice_set_pitch_hysteresis", {
+      pitchHysteresis: pitchHysteresis,
+    });
+  },
+
   setMinSourcePitch: async (minSourcePitch: number) => {
     await tauri.invoke<null>("beatrice_set_min_source_pitch", {
       minSourcePitch: minSourcePitch,
@@ -2,9 +2,13 @@ export const tauriStoreKey = "tauriStoreKey";
 export interface TauriStoreInterface {
   modelFolderPaths: string[];
 
+  audioHost: string | null;
   inputDevice: string | null;
   outputDevice: string | null;
   monitorDevice: string | null;
+  sampleRate: number | null;
+  bufferSize: number | null;
+  inputChannel: number | null;
 
   pitch: number | null;
   formantShift: number | null;
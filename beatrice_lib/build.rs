@@ -1,24 +1,49 @@
-use std::{fs::File, path::PathBuf};
+fn main() {
+    #[cfg(feature = "native")]
+    native::link();
+}
 
-use reqwest::blocking::Client;
+#[cfg(feature = "native")]
+mod native {
+    use std::{fs::File, path::PathBuf};
 
-fn main() {
-    // beatrice のライブラリをダウンロード
-    let lib_path = PathBuf::from("lib/beatrice.lib");
-
-    if !lib_path.exists() {
-        let lib_url = "https://huggingface.co/fierce-cats/beatrice-2.0.0-alpha/resolve/rc.0/rc.0/beatrice.lib";
-        let response = Client::new()
-            .get(lib_url)
-            .send()
-            .expect("Failed download lib");
-
-        let mut file = File::create("lib/beatrice.lib").expect("Failed create File");
-        let content = response.bytes().expect("Failed convert");
-        std::io::copy(&mut content.as_ref(), &mut file).expect("Failed copy");
+    use reqwest::blocking::Client;
+
+    const LIB_URL: &str =
+        "https://huggingface.co/fierce-cats/beatrice-2.0.0-alpha/resolve/rc.0/rc.0/beatrice.lib";
+
+    pub fn link() {
+        println!("cargo:rerun-if-env-changed=BEATRICE_LIB_DIR");
+
+        // BEATRICE_LIB_DIR が指定されていればそこにあるライブラリを使う
+        let lib_dir = match std::env::var_os("BEATRICE_LIB_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => {
+                let lib_dir =
+                    PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap()).join("lib");
+                download(&lib_dir);
+                lib_dir
+            }
+        };
+
+        // beatriceライブラリをリンク
+        println!("cargo:rustc-link-search=native={}", lib_dir.display());
+        println!("cargo:rustc-link-lib=static=beatrice");
     }
 
-    // beatriceライブラリをリンク
-    println!("cargo:rustc-link-search=native=beatrice_lib/lib");
-    println!("cargo:rustc-link-lib=static=beatrice");
+    // beatrice のライブラリをダウンロード
+    fn download(lib_dir: &std::path::Path) {
+        let lib_path = lib_dir.join("beatrice.lib");
+
+        if !lib_path.exists() {
+            let response = Client::new()
+                .get(LIB_URL)
+                .send()
+                .expect("Failed download lib");
+
+            let mut file = File::create(&lib_path).expect("Failed create File");
+            let content = response.bytes().expect("Failed convert");
+            std::io::copy(&mut content.as_ref(), &mut file).expect("Failed copy");
+        }
+    }
 }
@@ -1,7 +1,21 @@
 use std::path::Path;
 
-use crate::{BeatriceBeta0, BeatriceBeta1, BeatriceRC0, BeatriceToml, errors::BeatriceError};
-
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "mock")]
+use crate::MockBeatrice;
+#[cfg(feature = "native")]
+use crate::{BeatriceBeta0, BeatriceBeta1, BeatriceRC0};
+use crate::{
+    BeatriceResampler, BeatriceToml, CalibrationState, Capabilities, ChannelMix, PitchCalibration,
+    PitchCorrectionMode, PitchTelemetry, SpeakerWeight, VoiceParameters,
+    beatrice_toml::find_toml_path, errors::BeatriceError,
+};
+
+#[cfg_attr(
+    not(any(feature = "native", feature = "mock")),
+    allow(unused_variables, unreachable_code)
+)]
 pub fn new(
     model_folder: impl AsRef<Path>,
     in_sample_rate: f64,
@@ -11,29 +25,14 @@ pub fn new(
 ) -> Result<Box<dyn Beatrice>, BeatriceError> {
     let model_folder = model_folder.as_ref();
 
+    BeatriceResampler::validate(in_sample_rate, out_sample_rate, in_channel, out_channel)?;
+
     if !model_folder.is_dir() {
         return Err(BeatriceError::FileOpenError);
     }
 
     // tomlを探す
-    let mut toml_path = None;
-    for f in std::fs::read_dir(model_folder)?.flatten() {
-        let path = f.path();
-        if !path.is_file() {
-            continue;
-        }
-
-        let Some(ext) = path.extension() else {
-            continue;
-        };
-
-        if ext == "toml" {
-            toml_path = Some(path);
-            break;
-        }
-    }
-
-    let Some(toml_path) = toml_path else {
+    let Some(toml_path) = find_toml_path(model_folder)? else {
         return Err(BeatriceError::FileOpenError);
     };
 
@@ -43,39 +42,54 @@ pub fn new(
 
     // それぞれのバージョンのbeatriceを読み込む
     let beatrice: Box<dyn Beatrice> = match beatrice_toml.model.version.as_str() {
+        #[cfg(feature = "native")]
         "2.0.0-rc.0" => {
             let mut beatrice_rc0 = Box::new(BeatriceRC0::new(
                 in_sample_rate,
                 out_sample_rate,
                 in_channel,
                 out_channel,
-            ));
+            )?);
 
             beatrice_rc0.load_model(model_folder)?;
             beatrice_rc0
         }
+        #[cfg(feature = "native")]
         "2.0.0-beta.1" => {
             let mut beatrice_beta1 = Box::new(BeatriceBeta1::new(
                 in_sample_rate,
                 out_sample_rate,
                 in_channel,
                 out_channel,
-            ));
+            )?);
 
             beatrice_beta1.load_model(model_folder)?;
             beatrice_beta1
         }
+        #[cfg(feature = "native")]
         v if v.starts_with("2.0.0-alpha") => {
             let mut beatrice_beta0 = Box::new(BeatriceBeta0::new(
                 in_sample_rate,
                 out_sample_rate,
                 in_channel,
                 out_channel,
-            ));
+            )?);
 
             beatrice_beta0.load_model(model_folder)?;
             beatrice_beta0
         }
+        #[cfg(feature = "mock")]
+        "mock" => {
+            let mut mock = Box::new(MockBeatrice::new(
+                in_sample_rate,
+                out_sample_rate,
+                in_channel,
+                out_channel,
+            )?);
+
+            mock.load_model(model_folder)?;
+            mock
+        }
 
         _ => return Err(BeatriceError::FileOpenError),
     };
@@ -83,21 +97,65 @@ pub fn new(
     Ok(beatrice)
 }
 
+/// 入力から出力までの遅延
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Latency {
+    /// 入力のサンプリングレートでのフレーム数
+    pub input_frames: usize,
+    /// 出力のサンプリングレートでのフレーム数
+    pub output_frames: usize,
+}
+
 pub trait Beatrice: Send {
     fn infer(&mut self, input: &[f32]) -> Result<Vec<f32>, BeatriceError>;
+    /// 内部に残っている音声を無音で押し出して返す
+    fn flush(&mut self) -> Result<Vec<f32>, BeatriceError>;
+    /// リサンプラーとモデルの先読みを合わせた、入力から出力までの遅延
+    fn get_latency(&self) -> Latency;
+    /// 推論の内部状態 (コンテキストやバッファ) を初期化する。読み込んだモデルと設定はそのまま
+    fn reset(&mut self);
+    fn set_channel_mix(&mut self, channel_mix: ChannelMix) -> Result<(), BeatriceError>;
     fn get_model_path(&self) -> Option<&Path>;
     fn get_n_speaker(&self) -> Option<i32>;
+    fn get_parameters(&self) -> VoiceParameters;
+    /// 対応している設定とその範囲
+    fn get_capabilities(&self) -> Capabilities;
+    /// 有声の入力を `seconds` 秒分集めて、平均の音高などを求め始める
+    fn start_pitch_calibration(&mut self, seconds: f64) -> Result<(), BeatriceError>;
+    fn get_pitch_calibration(&self) -> CalibrationState;
+    /// 集めるのを止め、それまでの入力から求めた結果を返す (設定には反映しない)
+    fn finish_pitch_calibration(&mut self) -> Option<PitchCalibration>;
+    /// 有効にすると hop ごとのピッチの情報を溜める (取り出さなければ直近 1 秒分だけ残る)
+    fn set_pitch_telemetry(&mut self, enabled: bool);
+    /// 溜まっているピッチの情報を古い順に取り出す
+    fn take_pitch_telemetry(&mut self) -> Vec<PitchTelemetry>;
     fn set_target_speaker(&mut self, speaker: u32) -> Result<(), BeatriceError>;
-    fn set_formant_shift(&mut self, formant_shift: f64);
-    fn set_pitch_shift(&mut self, pitch_shift: f64);
-    fn set_average_source_pitch(&mut self, average_source_pitch: f64);
-    fn set_intonation_intensity(&mut self, intonation_intensity: f64);
-    fn set_pitch_correction(&mut self, pitch_correction: f64);
-    fn set_pitch_correction_type(&mut self, pitch_correction_type: i32);
+    /// 複数の話者を重み付きで混ぜた声にする。重みは合計が 1 になるように正規化する
+    ///
+    /// 話者の埋め込みは重みで補間する。RC.0 のコードブックは混ぜられないので一番重い話者のものを使う
+    fn set_speaker_mix(&mut self, speaker_mix: &[SpeakerWeight]) -> Result<(), BeatriceError>;
+    /// 範囲外の値は `BeatriceError::ParameterOutOfRange`、対応していない設定は
+    /// `BeatriceError::UnsupportedParameter` になる (範囲は `get_capabilities` で分かる)
+    fn set_formant_shift(&mut self, formant_shift: f64) -> Result<(), BeatriceError>;
+    /// 自動のピッチシフトが有効なら無効にする
+    fn set_pitch_shift(&mut self, pitch_shift: f64) -> Result<(), BeatriceError>;
+    /// 有効にすると、ターゲットの声の `average_pitch` (モデルの toml) と `average_source_pitch` の差を
+    /// ピッチシフトにする。`set_target_speaker` と `set_average_source_pitch` のたびに計算し直す
+    fn set_auto_pitch_shift(&mut self, auto_pitch_shift: bool) -> Result<(), BeatriceError>;
+    fn set_average_source_pitch(&mut self, average_source_pitch: f64) -> Result<(), BeatriceError>;
+    fn set_intonation_intensity(&mut self, intonation_intensity: f64) -> Result<(), BeatriceError>;
+    fn set_pitch_correction(&mut self, pitch_correction: f64) -> Result<(), BeatriceError>;
+    fn set_pitch_correction_mode(
+        &mut self,
+        pitch_correction_mode: PitchCorrectionMode,
+    ) -> Result<(), BeatriceError>;
+    fn set_pitch_glide_time(&mut self, pitch_glide_time: f64) -> Result<(), BeatriceError>;
+    fn set_pitch_median_window(&mut self, pitch_median_window: u32) -> Result<(), BeatriceError>;
+    fn set_pitch_hysteresis(&mut self, pitch_hysteresis: f64) -> Result<(), BeatriceError>;
 
     /* Only After RC.0 */
-    fn set_min_source_pitch(&mut self, min_source_pitch: f64);
-    fn set_max_source_pitch(&mut self, max_source_pitch: f64);
-    fn set_vq_num_neighbors(&mut self, vq_num_neighbors: i32);
+    fn set_min_source_pitch(&mut self, min_source_pitch: f64) -> Result<(), BeatriceError>;
+    fn set_max_source_pitch(&mut self, max_source_pitch: f64) -> Result<(), BeatriceError>;
+    fn set_vq_num_neighbors(&mut self, vq_num_neighbors: i32) -> Result<(), BeatriceError>;
     fn get_model_version(&self) -> &'static str;
 }
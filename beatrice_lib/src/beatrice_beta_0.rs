@@ -4,7 +4,25 @@ use std::{
     str::FromStr,
 };
 
-use crate::{beatrice::Beatrice, bindings::*, errors::BeatriceError, resampler::BeatriceResampler};
+use crate::{
+    beatrice::{Beatrice, Latency},
+    beatrice_toml::load_voice_average_pitches,
+    bindings::*,
+    calibration::{CalibrationState, PitchCalibration},
+    errors::BeatriceError,
+    formant::interpolate_formant_shift_embedding,
+    parameters::{Capabilities, PitchCorrectionMode, VoiceParameters},
+    resampler::{BeatriceResampler, ChannelMix},
+    speaker_mix::{SpeakerWeight, blend_embeddings},
+    telemetry::PitchTelemetry,
+    voice::VoiceState,
+};
+
+/// 2.0.0-alpha.2 のモデル自体の遅延 (24kHz のサンプル数)
+///
+/// beatrice.h は遅延を公開していないので、バインディングのバージョンごとにここで持つ。
+/// 1 hop 分としているが実測した値ではないので、ライブラリを差し替えたらインパルスを通して確かめる
+const BEATRICE_20A2_MODEL_DELAY: usize = BEATRICE_OUT_HOP_LENGTH as usize;
 
 struct BeatriceLibData {
     phone_extractor: *mut Beatrice20a2_PhoneExtractor,
@@ -15,32 +33,6 @@ struct BeatriceLibData {
     waveform_context: *mut Beatrice20a2_WaveformContext1,
 }
 
-pub struct BeatriceInfo {
-    pub target_speaker: i32,
-    pub formant_shift: f64,
-    pub pitch_shift: f64,
-    pub n_speakers: i32,
-    pub average_source_pitch: f64,
-    pub intonation_intensity: f64,
-    pub pitch_correction: f64,
-    pub pitch_correction_type: i32,
-}
-
-impl Default for BeatriceInfo {
-    fn default() -> Self {
-        Self {
-            target_speaker: 0,
-            formant_shift: 0.0,
-            pitch_shift: 0.0,
-            n_speakers: 0,
-            average_source_pitch: 60.0,
-            intonation_intensity: 1.0,
-            pitch_correction: 0.0,
-            pitch_correction_type: 0,
-        }
-    }
-}
-
 struct BeatriceModel {
     model_path: PathBuf,
     speaker_embeddings: Vec<f32>,
@@ -49,7 +41,7 @@ struct BeatriceModel {
 
 pub struct BeatriceBeta0 {
     model: Option<BeatriceModel>,
-    pub info: BeatriceInfo,
+    pub voice: VoiceState,
     lib: BeatriceLibData,
     resampler: BeatriceResampler,
 }
@@ -60,7 +52,11 @@ impl BeatriceBeta0 {
         out_sample_rate: f64,
         in_channel: u32,
         out_channel: u32,
-    ) -> Self {
+    ) -> Result<Self, BeatriceError> {
+        let mut resampler =
+            BeatriceResampler::new(in_sample_rate, out_sample_rate, in_channel, out_channel)?;
+        resampler.set_model_delay(BEATRICE_20A2_MODEL_DELAY);
+
         let lib = unsafe {
             BeatriceLibData {
                 phone_extractor: Beatrice20a2_CreatePhoneExtractor(),
@@ -72,24 +68,19 @@ impl BeatriceBeta0 {
             }
         };
 
-        let info = BeatriceInfo::default();
-
-        BeatriceBeta0 {
+        Ok(BeatriceBeta0 {
             model: None,
-            info,
+            voice: VoiceState::new(Capabilities::BETA),
             lib,
-            resampler: BeatriceResampler::new(
-                in_sample_rate,
-                out_sample_rate,
-                in_channel,
-                out_channel,
-            ),
-        }
+            resampler,
+        })
     }
 
     pub fn load_model(&mut self, model_path: impl AsRef<Path>) -> Result<(), BeatriceError> {
         let model_path = model_path.as_ref();
 
+        self.voice.voice_average_pitches = load_voice_average_pitches(model_path);
+
         let create_cstring = |file_name: &str| {
             CString::from_str(
                 model_path
@@ -154,14 +145,14 @@ impl BeatriceBeta0 {
             let file_name = create_cstring("speaker_embeddings.bin")?;
 
             let result = unsafe {
-                Beatrice20a2_ReadNSpeakers(file_name.as_ptr(), &mut self.info.n_speakers)
+                Beatrice20a2_ReadNSpeakers(file_name.as_ptr(), &mut self.voice.n_speakers)
             };
 
             if let Ok(err) = result.try_into() {
                 return Err(err);
             }
 
-            let new_size = ((self.info.n_speakers + 1) as usize)
+            let new_size = ((self.voice.n_speakers + 1) as usize)
                 * BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as usize;
             speaker_embeddings.resize(new_size, 0.0_f32);
 
@@ -213,20 +204,32 @@ impl BeatriceBeta0 {
             return Err(BeatriceError::ModelNotLoaded);
         }
 
-        let beatrice_input = self.resampler.convert_to_beatrice_input(input);
+        let beatrice_input = self.resampler.convert_to_beatrice_input(input)?;
 
         let mut processed = vec![];
-        for chunk in beatrice_input.chunks(BEATRICE_IN_HOP_LENGTH as usize) {
-            let mut buffer = [0.0; 160];
-
-            buffer[..chunk.len()].copy_from_slice(chunk);
-            processed.extend_from_slice(self.infer_slice(&buffer)?.as_ref());
+        for hop in beatrice_input.as_chunks().0 {
+            processed.extend_from_slice(self.infer_slice(hop)?.as_ref());
         }
 
-        let output = self.resampler.convert_from_beatrice_output(&processed);
+        let output = self.resampler.convert_from_beatrice_output(&processed)?;
         Ok(output)
     }
 
+    pub fn reset(&mut self) {
+        unsafe {
+            Beatrice20a2_DestroyPhoneContext1(self.lib.phone_context);
+            Beatrice20a2_DestroyPitchContext1(self.lib.pitch_context);
+            Beatrice20a2_DestroyWaveformContext1(self.lib.waveform_context);
+
+            self.lib.phone_context = Beatrice20a2_CreatePhoneContext1();
+            self.lib.pitch_context = Beatrice20a2_CreatePitchContext1();
+            self.lib.waveform_context = Beatrice20a2_CreateWaveformContext1();
+        }
+
+        self.resampler.reset();
+        self.voice.reset();
+    }
+
     fn infer_slice(
         &mut self,
         input: &[f32; BEATRICE_IN_HOP_LENGTH as usize],
@@ -253,109 +256,29 @@ impl BeatriceBeta0 {
             )
         };
 
-        const KPITCH_BINS_PER_SEMITONE: f64 = BEATRICE_PITCH_BINS_PER_OCTAVE as f64 / 12.0;
-
-        // PitchShift, IntonationIntensity
-        let mut tmp_quantized_pitch = self.info.average_source_pitch
-            + ((quantized_pitch as f64) - self.info.average_source_pitch)
-                * self.info.intonation_intensity
-            + KPITCH_BINS_PER_SEMITONE * self.info.pitch_shift;
-
-        // PitchCorrection
-        if self.info.pitch_correction != 0.0 {
-            let before_pitch_correction = tmp_quantized_pitch;
-
-            match self.info.pitch_correction_type {
-                0 => {
-                    let nearest_pitch = ((tmp_quantized_pitch / KPITCH_BINS_PER_SEMITONE).floor()
-                        + 0.5)
-                        * KPITCH_BINS_PER_SEMITONE;
-
-                    let normalized_delta =
-                        (tmp_quantized_pitch - nearest_pitch) * (2.0 / KPITCH_BINS_PER_SEMITONE);
-
-                    if normalized_delta.abs() < 1e-4 {
-                        tmp_quantized_pitch = nearest_pitch
-                    } else {
-                        tmp_quantized_pitch = nearest_pitch
-                            + normalized_delta
-                                * (normalized_delta.abs().powf(-self.info.pitch_correction))
-                                * (KPITCH_BINS_PER_SEMITONE / 2.0);
-                    }
-
-                    debug_assert!(
-                        (tmp_quantized_pitch
-                            - (tmp_quantized_pitch / KPITCH_BINS_PER_SEMITONE).round()
-                                * KPITCH_BINS_PER_SEMITONE)
-                            .abs()
-                            <= (before_pitch_correction
-                                - (tmp_quantized_pitch / KPITCH_BINS_PER_SEMITONE).round()
-                                    * KPITCH_BINS_PER_SEMITONE)
-                                + 1e-4
-                    )
-                }
-
-                1 => {
-                    let nearest_pitch = (tmp_quantized_pitch / KPITCH_BINS_PER_SEMITONE).round()
-                        * KPITCH_BINS_PER_SEMITONE;
-
-                    let normalized_delta =
-                        (tmp_quantized_pitch - nearest_pitch) * (2.0 / KPITCH_BINS_PER_SEMITONE);
-
-                    if self.info.pitch_correction > (1.0 - 1e-4) {
-                        tmp_quantized_pitch = nearest_pitch;
-                    } else if normalized_delta >= 0.0 {
-                        tmp_quantized_pitch = nearest_pitch
-                            + normalized_delta.powf(1.0 / (1.0 - self.info.pitch_correction))
-                                * (KPITCH_BINS_PER_SEMITONE / 2.0);
-                    } else {
-                        tmp_quantized_pitch = nearest_pitch
-                            - (-normalized_delta).powf(1.0 / (1.0 - self.info.pitch_correction))
-                                * (KPITCH_BINS_PER_SEMITONE / 2.0);
-                    }
-                    debug_assert!(
-                        (tmp_quantized_pitch - nearest_pitch).abs()
-                            <= (before_pitch_correction - nearest_pitch) + 1e-4
-                    );
-                }
-
-                _ => {
-                    debug_assert!(false);
-                }
-            }
-        }
-
-        quantized_pitch = {
-            let rounded = tmp_quantized_pitch.round() as i32;
-            rounded.clamp(1, BEATRICE_20A2_PITCH_BINS as i32 - 1)
-        };
+        quantized_pitch =
+            self.voice
+                .process_pitch(input, quantized_pitch, BEATRICE_20A2_PITCH_BINS);
 
         // speaker
         let mut speaker = [0.0_f32; BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as usize];
 
         if let Some(self_model) = &mut self.model {
-            unsafe {
-                let src_start = self.info.target_speaker as usize
-                    * BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as usize;
-                let src_slice = &self_model.speaker_embeddings
-                    [src_start..src_start + BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as usize];
-
-                std::ptr::copy_nonoverlapping(
-                    src_slice.as_ptr(),
-                    speaker.as_mut_ptr(),
-                    BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as usize,
-                );
-            }
+            blend_embeddings(
+                &mut speaker,
+                &self_model.speaker_embeddings,
+                &self.voice.speaker_mix,
+            );
 
-            let formant_shift_index = ((self.info.formant_shift * 2.0 + 4.0).round() as usize)
-                * BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as usize;
+            let mut formant_shift = [0.0_f32; BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as usize];
+            interpolate_formant_shift_embedding(
+                &mut formant_shift,
+                &self_model.formant_shift_embeddings,
+                self.voice.formant_shift,
+            );
 
-            for (i, take_speaker) in speaker
-                .iter_mut()
-                .enumerate()
-                .take(BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as usize)
-            {
-                *take_speaker += self_model.formant_shift_embeddings[formant_shift_index + i];
+            for (take_speaker, formant) in speaker.iter_mut().zip(formant_shift) {
+                *take_speaker += formant;
             }
         } else {
             return Err(BeatriceError::ModelNotLoaded);
@@ -399,54 +322,123 @@ impl Beatrice for BeatriceBeta0 {
         self.infer(input)
     }
 
+    fn flush(&mut self) -> Result<Vec<f32>, BeatriceError> {
+        let input = self.resampler.flush_input();
+        self.infer(&input)
+    }
+
+    fn get_latency(&self) -> Latency {
+        self.resampler.latency()
+    }
+
+    fn reset(&mut self) {
+        self.reset();
+    }
+
+    fn set_channel_mix(&mut self, channel_mix: ChannelMix) -> Result<(), BeatriceError> {
+        self.resampler.set_channel_mix(channel_mix)
+    }
+
     fn get_model_path(&self) -> Option<&Path> {
         self.model.as_ref().map(|m| m.model_path.as_path())
     }
 
     fn get_n_speaker(&self) -> Option<i32> {
-        self.model.as_ref().map(|_| self.info.n_speakers)
+        self.model.as_ref().map(|_| self.voice.n_speakers)
+    }
+
+    fn get_parameters(&self) -> VoiceParameters {
+        self.voice.parameters()
+    }
+
+    fn get_capabilities(&self) -> Capabilities {
+        self.voice.capabilities()
+    }
+
+    fn start_pitch_calibration(&mut self, seconds: f64) -> Result<(), BeatriceError> {
+        self.voice.start_pitch_calibration(seconds)
+    }
+
+    fn get_pitch_calibration(&self) -> CalibrationState {
+        self.voice.get_pitch_calibration()
+    }
+
+    fn finish_pitch_calibration(&mut self) -> Option<PitchCalibration> {
+        self.voice.finish_pitch_calibration()
+    }
+
+    fn set_pitch_telemetry(&mut self, enabled: bool) {
+        self.voice.set_pitch_telemetry(enabled);
+    }
+
+    fn take_pitch_telemetry(&mut self) -> Vec<PitchTelemetry> {
+        self.voice.take_pitch_telemetry()
     }
 
     fn set_target_speaker(&mut self, speaker: u32) -> Result<(), BeatriceError> {
-        let speaker = speaker as i32;
+        self.set_speaker_mix(&[SpeakerWeight::new(speaker, 1.0)])
+    }
 
-        if (self.info.n_speakers - 1) < speaker {
-            return Err(BeatriceError::SpeakerOutOfRange);
-        }
+    fn set_speaker_mix(&mut self, speaker_mix: &[SpeakerWeight]) -> Result<(), BeatriceError> {
+        let n_speakers = self.voice.n_speakers.max(0) as usize;
+        self.voice.set_speaker_mix(speaker_mix, n_speakers)
+    }
+
+    fn set_formant_shift(&mut self, formant_shift: f64) -> Result<(), BeatriceError> {
+        self.voice.set_formant_shift(formant_shift)
+    }
 
-        self.info.target_speaker = speaker;
+    fn set_pitch_shift(&mut self, pitch_shift: f64) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_shift(pitch_shift)
+    }
+
+    fn set_auto_pitch_shift(&mut self, auto_pitch_shift: bool) -> Result<(), BeatriceError> {
+        self.voice.set_auto_pitch_shift(auto_pitch_shift);
         Ok(())
     }
 
-    fn set_formant_shift(&mut self, formant_shift: f64) {
-        self.info.formant_shift = formant_shift;
+    fn set_average_source_pitch(&mut self, average_source_pitch: f64) -> Result<(), BeatriceError> {
+        self.voice.set_average_source_pitch(average_source_pitch)
+    }
+
+    fn set_intonation_intensity(&mut self, intonation_intensity: f64) -> Result<(), BeatriceError> {
+        self.voice.set_intonation_intensity(intonation_intensity)
     }
 
-    fn set_pitch_shift(&mut self, pitch_shift: f64) {
-        self.info.pitch_shift = pitch_shift;
+    fn set_pitch_correction(&mut self, pitch_correction: f64) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_correction(pitch_correction)
     }
 
-    fn set_average_source_pitch(&mut self, average_source_pitch: f64) {
-        self.info.average_source_pitch = average_source_pitch;
+    fn set_pitch_correction_mode(
+        &mut self,
+        pitch_correction_mode: PitchCorrectionMode,
+    ) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_correction_mode(pitch_correction_mode)
     }
 
-    fn set_intonation_intensity(&mut self, intonation_intensity: f64) {
-        self.info.intonation_intensity = intonation_intensity;
+    fn set_pitch_glide_time(&mut self, pitch_glide_time: f64) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_glide_time(pitch_glide_time)
     }
 
-    fn set_pitch_correction(&mut self, pitch_correction: f64) {
-        self.info.pitch_correction = pitch_correction;
+    fn set_pitch_median_window(&mut self, pitch_median_window: u32) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_median_window(pitch_median_window)
     }
 
-    fn set_pitch_correction_type(&mut self, pitch_correction_type: i32) {
-        self.info.pitch_correction_type = pitch_correction_type;
+    fn set_pitch_hysteresis(&mut self, pitch_hysteresis: f64) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_hysteresis(pitch_hysteresis)
     }
 
-    fn set_min_source_pitch(&mut self, _min_source_pitch: f64) {}
+    fn set_min_source_pitch(&mut self, min_source_pitch: f64) -> Result<(), BeatriceError> {
+        self.voice.set_min_source_pitch(min_source_pitch)
+    }
 
-    fn set_max_source_pitch(&mut self, _max_source_pitch: f64) {}
+    fn set_max_source_pitch(&mut self, max_source_pitch: f64) -> Result<(), BeatriceError> {
+        self.voice.set_max_source_pitch(max_source_pitch)
+    }
 
-    fn set_vq_num_neighbors(&mut self, _vq_num_neighbors: i32) {}
+    fn set_vq_num_neighbors(&mut self, vq_num_neighbors: i32) -> Result<(), BeatriceError> {
+        self.voice.set_vq_num_neighbors(vq_num_neighbors)
+    }
 
     fn get_model_version(&self) -> &'static str {
         "2.0.0-alpha"
@@ -0,0 +1,553 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    BeatriceToml,
+    beatrice::{Beatrice, Latency},
+    beatrice_toml::find_toml_path,
+    calibration::{CalibrationState, PitchCalibration},
+    constants::{BEATRICE_IN_HOP_LENGTH, BEATRICE_IN_SAMPLE_RATE, BEATRICE_OUT_HOP_LENGTH},
+    errors::BeatriceError,
+    parameters::{Capabilities, PitchCorrectionMode, VoiceParameters},
+    pitch,
+    resampler::{BeatriceResampler, ChannelMix},
+    speaker_mix::SpeakerWeight,
+    telemetry::PitchTelemetry,
+    voice::VoiceState,
+};
+
+/// モックは hop の中で完結するのでモデル自体の遅延はない
+const MODEL_DELAY: usize = 0;
+
+/// quantized pitch のビン数 (RC.0 のモデルと同じ)
+const PITCH_BINS: u32 = 448;
+
+#[derive(Debug)]
+struct BeatriceModel {
+    model_path: PathBuf,
+}
+
+/// ネイティブライブラリを使わない `Beatrice` の実装
+///
+/// 16kHz の 1 hop (160 サンプル) を線形補間で 24kHz の 1 hop (240 サンプル) に伸ばし、
+/// `target_speaker`, `pitch_shift`, `formant_shift` から決まるゲインを掛けて返す。
+/// 音高はゼロ交差から推定し、ネイティブのバックエンドと同じピッチの処理に通す
+pub struct MockBeatrice {
+    model: Option<BeatriceModel>,
+    pub voice: VoiceState,
+    resampler: BeatriceResampler,
+}
+
+impl MockBeatrice {
+    pub fn new(
+        in_sample_rate: f64,
+        out_sample_rate: f64,
+        in_channel: u32,
+        out_channel: u32,
+    ) -> Result<Self, BeatriceError> {
+        let mut resampler =
+            BeatriceResampler::new(in_sample_rate, out_sample_rate, in_channel, out_channel)?;
+        resampler.set_model_delay(MODEL_DELAY);
+
+        Ok(MockBeatrice {
+            model: None,
+            voice: VoiceState::new(Capabilities::RC0),
+            resampler,
+        })
+    }
+
+    /// フォルダ内に toml があればその voice の数を話者数とする (無ければ 1 話者)
+    pub fn load_model(&mut self, model_path: impl AsRef<Path>) -> Result<(), BeatriceError> {
+        let model_path = model_path.as_ref();
+
+        self.voice.n_speakers = match find_toml_path(model_path).ok().flatten() {
+            Some(toml_path) => {
+                let Ok(beatrice_toml) = BeatriceToml::load_from_tomlpath(toml_path) else {
+                    return Err(BeatriceError::FileOpenError);
+                };
+
+                self.voice.voice_average_pitches = beatrice_toml.voice_average_pitches();
+                beatrice_toml.voice.len().max(1) as i32
+            }
+            None => 1,
+        };
+
+        self.model = Some(BeatriceModel {
+            model_path: model_path.to_path_buf(),
+        });
+
+        Ok(())
+    }
+
+    pub fn infer(&mut self, input: &[f32]) -> Result<Vec<f32>, BeatriceError> {
+        if self.model.is_none() {
+            return Err(BeatriceError::ModelNotLoaded);
+        }
+
+        let beatrice_input = self.resampler.convert_to_beatrice_input(input)?;
+
+        let mut processed = vec![];
+        for hop in beatrice_input.as_chunks().0 {
+            processed.extend_from_slice(self.infer_slice(hop)?.as_ref());
+        }
+
+        let output = self.resampler.convert_from_beatrice_output(&processed)?;
+        Ok(output)
+    }
+
+    pub fn reset(&mut self) {
+        self.resampler.reset();
+        self.voice.reset();
+    }
+
+    fn gain(&self) -> f32 {
+        let speaker_gain = self
+            .voice
+            .speaker_mix
+            .iter()
+            .map(|m| m.weight / (1.0 + m.speaker as f64))
+            .sum::<f64>();
+        let shift_gain = 2.0_f64.powf((self.voice.pitch_shift + self.voice.formant_shift) / 12.0);
+
+        (speaker_gain * shift_gain) as f32
+    }
+
+    /// 立ち上がりのゼロ交差の間隔から音高 (MIDI ノート番号) を推定する
+    fn estimate_note(input: &[f32; BEATRICE_IN_HOP_LENGTH as usize]) -> Option<f64> {
+        let crossings = input
+            .windows(2)
+            .enumerate()
+            .filter(|(_, w)| w[0] < 0.0 && 0.0 <= w[1])
+            .map(|(i, w)| i as f64 + (w[0] / (w[0] - w[1])) as f64)
+            .collect::<Vec<_>>();
+
+        let (first, last) = (crossings.first()?, crossings.last()?);
+        if crossings.len() < 2 {
+            return None;
+        }
+
+        let period = (last - first) / (crossings.len() - 1) as f64;
+        let frequency = BEATRICE_IN_SAMPLE_RATE as f64 / period;
+
+        Some(69.0 + 12.0 * (frequency / 440.0).log2())
+    }
+
+    fn infer_slice(
+        &mut self,
+        input: &[f32; BEATRICE_IN_HOP_LENGTH as usize],
+    ) -> Result<[f32; BEATRICE_OUT_HOP_LENGTH as usize], BeatriceError> {
+        // 実際のモデルと同じく、推定した音高を quantized pitch にしてからピッチの処理に通す
+        let quantized_pitch = Self::estimate_note(input).map_or(0, |note| {
+            (pitch::note_to_quantized_pitch(note).round() as i32).clamp(1, PITCH_BINS as i32 - 1)
+        });
+        self.voice.process_pitch(input, quantized_pitch, PITCH_BINS);
+
+        let gain = self.gain();
+        let step = BEATRICE_IN_HOP_LENGTH as f32 / BEATRICE_OUT_HOP_LENGTH as f32;
+
+        let mut output = [0.0; 240];
+        for (i, out) in output.iter_mut().enumerate() {
+            let pos = i as f32 * step;
+            let idx = pos as usize;
+            let frac = pos - idx as f32;
+
+            let a = input[idx];
+            let b = input[(idx + 1).min(input.len() - 1)];
+
+            *out = (a + (b - a) * frac) * gain;
+        }
+
+        Ok(output)
+    }
+}
+
+impl Beatrice for MockBeatrice {
+    fn infer(&mut self, input: &[f32]) -> Result<Vec<f32>, BeatriceError> {
+        self.infer(input)
+    }
+
+    fn flush(&mut self) -> Result<Vec<f32>, BeatriceError> {
+        let input = self.resampler.flush_input();
+        self.infer(&input)
+    }
+
+    fn get_latency(&self) -> Latency {
+        self.resampler.latency()
+    }
+
+    fn reset(&mut self) {
+        self.reset();
+    }
+
+    fn set_channel_mix(&mut self, channel_mix: ChannelMix) -> Result<(), BeatriceError> {
+        self.resampler.set_channel_mix(channel_mix)
+    }
+
+    fn get_model_path(&self) -> Option<&Path> {
+        self.model.as_ref().map(|m| m.model_path.as_path())
+    }
+
+    fn get_n_speaker(&self) -> Option<i32> {
+        self.model.as_ref().map(|_| self.voice.n_speakers)
+    }
+
+    fn get_parameters(&self) -> VoiceParameters {
+        self.voice.parameters()
+    }
+
+    fn get_capabilities(&self) -> Capabilities {
+        self.voice.capabilities()
+    }
+
+    fn start_pitch_calibration(&mut self, seconds: f64) -> Result<(), BeatriceError> {
+        self.voice.start_pitch_calibration(seconds)
+    }
+
+    fn get_pitch_calibration(&self) -> CalibrationState {
+        self.voice.get_pitch_calibration()
+    }
+
+    fn finish_pitch_calibration(&mut self) -> Option<PitchCalibration> {
+        self.voice.finish_pitch_calibration()
+    }
+
+    fn set_pitch_telemetry(&mut self, enabled: bool) {
+        self.voice.set_pitch_telemetry(enabled);
+    }
+
+    fn take_pitch_telemetry(&mut self) -> Vec<PitchTelemetry> {
+        self.voice.take_pitch_telemetry()
+    }
+
+    fn set_target_speaker(&mut self, speaker: u32) -> Result<(), BeatriceError> {
+        self.set_speaker_mix(&[SpeakerWeight::new(speaker, 1.0)])
+    }
+
+    fn set_speaker_mix(&mut self, speaker_mix: &[SpeakerWeight]) -> Result<(), BeatriceError> {
+        let n_speakers = self.voice.n_speakers.max(0) as usize;
+        self.voice.set_speaker_mix(speaker_mix, n_speakers)
+    }
+
+    fn set_formant_shift(&mut self, formant_shift: f64) -> Result<(), BeatriceError> {
+        self.voice.set_formant_shift(formant_shift)
+    }
+
+    fn set_pitch_shift(&mut self, pitch_shift: f64) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_shift(pitch_shift)
+    }
+
+    fn set_auto_pitch_shift(&mut self, auto_pitch_shift: bool) -> Result<(), BeatriceError> {
+        self.voice.set_auto_pitch_shift(auto_pitch_shift);
+        Ok(())
+    }
+
+    fn set_average_source_pitch(&mut self, average_source_pitch: f64) -> Result<(), BeatriceError> {
+        self.voice.set_average_source_pitch(average_source_pitch)
+    }
+
+    fn set_intonation_intensity(&mut self, intonation_intensity: f64) -> Result<(), BeatriceError> {
+        self.voice.set_intonation_intensity(intonation_intensity)
+    }
+
+    fn set_pitch_correction(&mut self, pitch_correction: f64) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_correction(pitch_correction)
+    }
+
+    fn set_pitch_correction_mode(
+        &mut self,
+        pitch_correction_mode: PitchCorrectionMode,
+    ) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_correction_mode(pitch_correction_mode)
+    }
+
+    fn set_pitch_glide_time(&mut self, pitch_glide_time: f64) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_glide_time(pitch_glide_time)
+    }
+
+    fn set_pitch_median_window(&mut self, pitch_median_window: u32) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_median_window(pitch_median_window)
+    }
+
+    fn set_pitch_hysteresis(&mut self, pitch_hysteresis: f64) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_hysteresis(pitch_hysteresis)
+    }
+
+    fn set_min_source_pitch(&mut self, min_source_pitch: f64) -> Result<(), BeatriceError> {
+        self.voice.set_min_source_pitch(min_source_pitch)
+    }
+
+    fn set_max_source_pitch(&mut self, max_source_pitch: f64) -> Result<(), BeatriceError> {
+        self.voice.set_max_source_pitch(max_source_pitch)
+    }
+
+    fn set_vq_num_neighbors(&mut self, vq_num_neighbors: i32) -> Result<(), BeatriceError> {
+        self.voice.set_vq_num_neighbors(vq_num_neighbors)
+    }
+
+    fn get_model_version(&self) -> &'static str {
+        "mock"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Beatrice, BeatriceError, MockBeatrice, PitchCorrectionMode, SpeakerWeight};
+
+    fn loaded(in_sample_rate: f64, out_sample_rate: f64) -> MockBeatrice {
+        let mut beatrice = MockBeatrice::new(in_sample_rate, out_sample_rate, 1, 1).unwrap();
+        beatrice.load_model("mock").unwrap();
+        beatrice
+    }
+
+    #[test]
+    fn test_not_loaded() {
+        let mut beatrice = MockBeatrice::new(16000.0, 24000.0, 1, 1).unwrap();
+        assert!(beatrice.infer(&[0.0; 160]).is_err());
+        assert_eq!(beatrice.get_n_speaker(), None);
+    }
+
+    #[test]
+    fn test_hop() {
+        let mut beatrice = loaded(16000.0, 24000.0);
+
+        let input = [0.5_f32; 160];
+        let output = beatrice.infer_slice(&input).unwrap();
+        assert!(output.iter().all(|v| (v - 0.5).abs() < 1e-6));
+
+        beatrice.set_pitch_shift(12.0).unwrap();
+        let output = beatrice.infer_slice(&input).unwrap();
+        assert!(output.iter().all(|v| (v - 1.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_infer_length() {
+        let mut beatrice = loaded(48000.0, 48000.0);
+        let latency = beatrice.get_latency();
+
+        // リサンプラの遅延ぶんが溜まるまでは出力が短い
+        let mut total = 0;
+        for _ in 0..2 {
+            total += beatrice.infer(&[0.0; 480]).unwrap().len();
+        }
+        assert!(total < 960);
+        assert!(total + latency.output_frames >= 960);
+
+        // 以降は入力と同じ長さを返す
+        for _ in 0..100 {
+            assert_eq!(beatrice.infer(&[0.0; 480]).unwrap().len(), 480);
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let input = (0..480).map(|i| (i as f32 * 0.1).sin()).collect::<Vec<_>>();
+
+        let mut fresh = loaded(48000.0, 48000.0);
+        let expected = fresh.infer(&input).unwrap();
+
+        let mut beatrice = loaded(48000.0, 48000.0);
+        beatrice.infer(&[1.0; 1000]).unwrap();
+        beatrice.reset();
+
+        assert_eq!(beatrice.infer(&input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_speaker_out_of_range() {
+        let mut beatrice = loaded(16000.0, 24000.0);
+        assert!(beatrice.set_target_speaker(0).is_ok());
+        assert!(beatrice.set_target_speaker(1).is_err());
+    }
+
+    #[test]
+    fn test_get_parameters() {
+        let mut beatrice = loaded(16000.0, 24000.0);
+        beatrice.set_pitch_shift(3.0).unwrap();
+        beatrice.set_vq_num_neighbors(4).unwrap();
+
+        let parameters = beatrice.get_parameters();
+        assert_eq!(parameters.target_speaker, 0);
+        assert_eq!(parameters.pitch_shift, 3.0);
+        assert_eq!(parameters.vq_num_neighbors, Some(4));
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let beatrice = loaded(16000.0, 24000.0);
+        let capabilities = beatrice.get_capabilities();
+
+        // 埋め込みの間を補間するので 0.5 刻みに限らない
+        let formant = capabilities.formant_shift;
+        assert_eq!((formant.min, formant.max), (-2.0, 2.0));
+        assert!(formant.step < 0.5);
+        assert!(capabilities.vq_num_neighbors.is_some());
+    }
+
+    #[test]
+    fn test_parameter_out_of_range() {
+        let mut beatrice = loaded(16000.0, 24000.0);
+
+        assert!(matches!(
+            beatrice.set_formant_shift(3.0),
+            Err(BeatriceError::ParameterOutOfRange("formant_shift", _))
+        ));
+        assert!(beatrice.set_pitch_shift(f64::NAN).is_err());
+        assert!(beatrice.set_vq_num_neighbors(9).is_err());
+
+        assert_eq!(beatrice.get_parameters().formant_shift, 0.0);
+    }
+
+    // 平均ピッチ 45.2 と 64.0 の 2 人の声を持つモデルフォルダを作る
+    fn two_voice_model(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("beatrice.toml"),
+            r#"
+[model]
+version = "mock"
+name = "mock"
+description = ""
+
+[voice.0]
+name = "low"
+description = ""
+average_pitch = 45.2
+
+[voice.1]
+name = "high"
+description = ""
+average_pitch = 64.0
+"#,
+        )
+        .unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn test_auto_pitch_shift() {
+        let dir = two_voice_model("beatrice_lib_test_auto_pitch_shift");
+
+        let mut beatrice = MockBeatrice::new(16000.0, 24000.0, 1, 1).unwrap();
+        beatrice.load_model(&dir).unwrap();
+        beatrice.set_pitch_shift(3.0).unwrap();
+
+        // 無効の間は何も変わらない
+        beatrice.set_target_speaker(1).unwrap();
+        assert_eq!(beatrice.get_parameters().pitch_shift, 3.0);
+
+        beatrice.set_auto_pitch_shift(true).unwrap();
+        assert_eq!(beatrice.get_parameters().pitch_shift, 12.0);
+
+        beatrice.set_target_speaker(0).unwrap();
+        assert_eq!(beatrice.get_parameters().pitch_shift, -7.0);
+
+        beatrice.set_average_source_pitch(60.0).unwrap();
+        assert_eq!(beatrice.get_parameters().pitch_shift, -15.0);
+
+        // 手で決めると自動は止まり、後から上書きされない
+        beatrice.set_pitch_shift(2.0).unwrap();
+        assert!(!beatrice.get_parameters().auto_pitch_shift);
+        beatrice.set_target_speaker(1).unwrap();
+        assert_eq!(beatrice.get_parameters().pitch_shift, 2.0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // 16kHz で 1 hop 分の正弦波
+    fn sine(frequency: f32) -> [f32; 160] {
+        std::array::from_fn(|i| {
+            (i as f32 / 16000.0 * frequency * std::f32::consts::TAU).sin() * 0.5
+        })
+    }
+
+    #[test]
+    fn test_pitch_telemetry() {
+        let mut beatrice = loaded(16000.0, 24000.0);
+        beatrice.set_pitch_shift(12.0).unwrap();
+
+        // 220Hz = 57
+        let input = sine(220.0);
+
+        beatrice.infer_slice(&input).unwrap();
+        assert!(beatrice.take_pitch_telemetry().is_empty());
+
+        beatrice.set_pitch_telemetry(true);
+        beatrice.infer_slice(&input).unwrap();
+        beatrice.infer_slice(&[0.0; 160]).unwrap();
+
+        let telemetry = beatrice.take_pitch_telemetry();
+        assert_eq!(telemetry.len(), 2);
+
+        let source_pitch = telemetry[0].source_pitch.unwrap();
+        assert!((source_pitch - 57.0).abs() < 0.1);
+        assert_eq!(telemetry[0].output_pitch, Some(source_pitch + 12.0));
+        assert!(telemetry[0].voiced);
+        assert!(!telemetry[1].voiced);
+    }
+
+    #[test]
+    fn test_pitch_processing() {
+        let mut beatrice = loaded(16000.0, 24000.0);
+        beatrice.set_pitch_telemetry(true);
+        beatrice
+            .set_pitch_correction_mode(PitchCorrectionMode::Pull)
+            .unwrap();
+        beatrice.set_pitch_correction(1.0).unwrap();
+
+        // 224Hz ≒ 57.3 は補正で 57 に寄る
+        let input = sine(224.0);
+        beatrice.infer_slice(&input).unwrap();
+
+        let telemetry = beatrice.take_pitch_telemetry();
+        assert!((telemetry[0].source_pitch.unwrap() - 57.3).abs() < 0.2);
+        assert_eq!(telemetry[0].output_pitch, Some(57.0));
+
+        // グライド中は 1 hop では目標 (69) まで届かない
+        beatrice.set_pitch_glide_time(100.0).unwrap();
+        beatrice.set_pitch_shift(12.0).unwrap();
+        beatrice.infer_slice(&input).unwrap();
+
+        let output_pitch = beatrice.take_pitch_telemetry()[0].output_pitch.unwrap();
+        assert!(57.0 < output_pitch && output_pitch < 69.0);
+    }
+
+    #[test]
+    fn test_speaker_mix() {
+        let dir = two_voice_model("beatrice_lib_test_speaker_mix");
+
+        let mut beatrice = MockBeatrice::new(16000.0, 24000.0, 1, 1).unwrap();
+        beatrice.load_model(&dir).unwrap();
+        beatrice.set_auto_pitch_shift(true).unwrap();
+
+        beatrice
+            .set_speaker_mix(&[SpeakerWeight::new(0, 1.0), SpeakerWeight::new(1, 3.0)])
+            .unwrap();
+
+        let parameters = beatrice.get_parameters();
+        assert_eq!(parameters.target_speaker, 1);
+        assert_eq!(
+            parameters.speaker_mix,
+            [SpeakerWeight::new(1, 0.75), SpeakerWeight::new(0, 0.25)]
+        );
+        // 45.2 * 0.25 + 64.0 * 0.75 = 59.3 なので 52 から +7
+        assert_eq!(parameters.pitch_shift, 7.0);
+
+        assert!(matches!(
+            beatrice.set_speaker_mix(&[SpeakerWeight::new(2, 1.0)]),
+            Err(BeatriceError::SpeakerOutOfRange)
+        ));
+        assert_eq!(
+            beatrice.get_parameters().speaker_mix,
+            parameters.speaker_mix
+        );
+
+        beatrice.set_target_speaker(0).unwrap();
+        assert_eq!(
+            beatrice.get_parameters().speaker_mix,
+            [SpeakerWeight::new(0, 1.0)]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
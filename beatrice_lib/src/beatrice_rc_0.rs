@@ -4,7 +4,25 @@ use std::{
     str::FromStr,
 };
 
-use crate::{beatrice::Beatrice, bindings::*, errors::BeatriceError, resampler::BeatriceResampler};
+use crate::{
+    beatrice::{Beatrice, Latency},
+    beatrice_toml::load_voice_average_pitches,
+    bindings::*,
+    calibration::{CalibrationState, PitchCalibration},
+    errors::BeatriceError,
+    formant::interpolate_formant_shift_embedding,
+    parameters::{Capabilities, PitchCorrectionMode, VoiceParameters},
+    resampler::{BeatriceResampler, ChannelMix},
+    speaker_mix::{SpeakerWeight, blend_embeddings, normalize_speaker_mix},
+    telemetry::PitchTelemetry,
+    voice::VoiceState,
+};
+
+/// 2.0.0-rc.0 のモデル自体の遅延 (24kHz のサンプル数)
+///
+/// beatrice.h は遅延を公開していないので、バインディングのバージョンごとにここで持つ。
+/// 1 hop 分としているが実測した値ではないので、ライブラリを差し替えたらインパルスを通して確かめる
+const BEATRICE_20RC0_MODEL_DELAY: usize = BEATRICE_OUT_HOP_LENGTH as usize;
 
 struct BeatriceLibData {
     phone_extractor: *mut Beatrice20rc0_PhoneExtractor,
@@ -16,6 +34,9 @@ struct BeatriceLibData {
     additive_speaker_embeddings: Vec<f32>,
     formant_shift_embeddings: Vec<f32>,
     key_value_speaker_embeddings: Vec<f32>,
+    mixed_additive_speaker_embedding: Vec<f32>,
+    mixed_key_value_speaker_embedding: Vec<f32>,
+    interpolated_formant_shift_embedding: Vec<f32>,
 
     phone_context: *mut Beatrice20rc0_PhoneContext1,
     pitch_context: *mut Beatrice20rc0_PitchContext1,
@@ -26,39 +47,6 @@ struct BeatriceLibData {
     is_ready_to_set_speaker: bool,
 }
 
-#[derive(Debug)]
-pub struct BeatriceInfo {
-    pub target_speaker: i32,
-    pub formant_shift: f64,
-    pub pitch_shift: f64,
-    pub n_speakers: i32,
-    pub average_source_pitch: f64,
-    pub intonation_intensity: f64,
-    pub pitch_correction: f64,
-    pub pitch_correction_type: i32,
-    pub min_source_pitch: f64,
-    pub max_source_pitch: f64,
-    pub vq_num_neighbors: i32,
-}
-
-impl Default for BeatriceInfo {
-    fn default() -> Self {
-        Self {
-            target_speaker: 0,
-            formant_shift: 0.0,
-            pitch_shift: 0.0,
-            n_speakers: 0,
-            average_source_pitch: 52.0,
-            intonation_intensity: 1.0,
-            pitch_correction: 0.0,
-            pitch_correction_type: 0,
-            min_source_pitch: 33.125,
-            max_source_pitch: 80.875,
-            vq_num_neighbors: 0,
-        }
-    }
-}
-
 #[derive(Debug)]
 struct BeatriceModel {
     model_path: PathBuf,
@@ -66,7 +54,7 @@ struct BeatriceModel {
 
 pub struct BeatriceRC0 {
     model: Option<BeatriceModel>,
-    pub info: BeatriceInfo,
+    pub voice: VoiceState,
     lib: BeatriceLibData,
     resamplers: BeatriceResampler,
 }
@@ -78,7 +66,11 @@ impl BeatriceRC0 {
         out_sample_rate: f64,
         in_channel: u32,
         out_channel: u32,
-    ) -> BeatriceRC0 {
+    ) -> Result<Self, BeatriceError> {
+        let mut resamplers =
+            BeatriceResampler::new(in_sample_rate, out_sample_rate, in_channel, out_channel)?;
+        resamplers.set_model_delay(BEATRICE_20RC0_MODEL_DELAY);
+
         let lib = unsafe {
             BeatriceLibData {
                 phone_extractor: Beatrice20rc0_CreatePhoneExtractor(),
@@ -90,6 +82,9 @@ impl BeatriceRC0 {
                 additive_speaker_embeddings: vec![],
                 formant_shift_embeddings: vec![],
                 key_value_speaker_embeddings: vec![],
+                mixed_additive_speaker_embedding: vec![],
+                mixed_key_value_speaker_embedding: vec![],
+                interpolated_formant_shift_embedding: vec![],
 
                 phone_context: Beatrice20rc0_CreatePhoneContext1(),
                 pitch_context: Beatrice20rc0_CreatePitchContext1(),
@@ -101,19 +96,12 @@ impl BeatriceRC0 {
             }
         };
 
-        let info = BeatriceInfo::default();
-
-        BeatriceRC0 {
+        Ok(BeatriceRC0 {
             model: None,
-            info,
+            voice: VoiceState::new(Capabilities::RC0),
             lib,
-            resamplers: BeatriceResampler::new(
-                in_sample_rate,
-                out_sample_rate,
-                in_channel,
-                out_channel,
-            ),
-        }
+            resamplers,
+        })
     }
 
     pub fn infer(&mut self, input: &[f32]) -> Result<Vec<f32>, BeatriceError> {
@@ -121,23 +109,22 @@ impl BeatriceRC0 {
             return Err(BeatriceError::ModelNotLoaded);
         }
 
-        let beatrice_input = self.resamplers.convert_to_beatrice_input(input);
+        let beatrice_input = self.resamplers.convert_to_beatrice_input(input)?;
 
         let mut processed = vec![];
-        for chunk in beatrice_input.chunks(BEATRICE_IN_HOP_LENGTH as usize) {
-            let mut buffer = [0.0; 160];
-
-            buffer[..chunk.len()].copy_from_slice(chunk);
-            processed.extend_from_slice(self.infer_slice(&buffer)?.as_ref());
+        for hop in beatrice_input.as_chunks().0 {
+            processed.extend_from_slice(self.infer_slice(hop)?.as_ref());
         }
 
-        let output = self.resamplers.convert_from_beatrice_output(&processed);
+        let output = self.resamplers.convert_from_beatrice_output(&processed)?;
         Ok(output)
     }
 
     pub fn load_model(&mut self, model_path: impl AsRef<Path>) -> Result<(), BeatriceError> {
         let model_path = model_path.as_ref();
 
+        self.voice.voice_average_pitches = load_voice_average_pitches(model_path);
+
         let create_cstring = |file_name: &str| {
             CString::from_str(
                 model_path
@@ -217,14 +204,14 @@ impl BeatriceRC0 {
             let file_name = create_cstring("speaker_embeddings.bin")?;
 
             let result = unsafe {
-                Beatrice20rc0_ReadNSpeakers(file_name.as_ptr(), &mut self.info.n_speakers)
+                Beatrice20rc0_ReadNSpeakers(file_name.as_ptr(), &mut self.voice.n_speakers)
             };
 
             if let Ok(err) = result.try_into() {
                 return Err(err);
             }
 
-            let n_speakers_plus_1 = (self.info.n_speakers + 1) as usize;
+            let n_speakers_plus_1 = (self.voice.n_speakers + 1) as usize;
 
             self.lib.codebooks.resize(
                 n_speakers_plus_1
@@ -264,53 +251,102 @@ impl BeatriceRC0 {
             }
         }
 
+        // 埋め込みを設定する関数はモデルが読み込まれているかを見るので、先に入れる
+        self.model = Some(BeatriceModel {
+            model_path: model_path.to_path_buf(),
+        });
+
         self.lib.is_ready_to_set_speaker = true;
         self.set_target_speaker(0)?;
         while self.set_key_value_speaker_embedding() {}
 
-        self.model = Some(BeatriceModel {
-            model_path: model_path.to_path_buf(),
-        });
+        self.set_formant_shift(self.voice.formant_shift)?;
 
         Ok(())
     }
 
-    pub fn set_min_source_pitch(&mut self, min_source_pitch: f64) {
-        self.info.min_source_pitch = min_source_pitch.clamp(0.0, 128.0);
+    pub fn reset(&mut self) {
+        unsafe {
+            Beatrice20rc0_DestroyPhoneContext1(self.lib.phone_context);
+            Beatrice20rc0_DestroyPitchContext1(self.lib.pitch_context);
+            Beatrice20rc0_DestroyWaveformContext1(self.lib.waveform_context);
+            Beatrice20rc0_DestroyEmbeddingContext(self.lib.embedding_context);
+
+            self.lib.phone_context = Beatrice20rc0_CreatePhoneContext1();
+            self.lib.pitch_context = Beatrice20rc0_CreatePitchContext1();
+            self.lib.waveform_context = Beatrice20rc0_CreateWaveformContext1();
+            self.lib.embedding_context = Beatrice20rc0_CreateEmbeddingContext();
+        }
+        self.lib.key_value_speaker_embedding_set_count = 0;
+
+        self.resamplers.reset();
+        self.voice.reset();
+
+        // 新しいコンテキストに今の設定を入れ直す
+        if self.model.is_some() {
+            let _ = self.set_speaker_mix(&self.voice.speaker_mix.clone());
+            while self.set_key_value_speaker_embedding() {}
+
+            let _ = self.set_formant_shift(self.voice.formant_shift);
+            if let Some(min_source_pitch) = self.voice.min_source_pitch {
+                let _ = self.set_min_source_pitch(min_source_pitch);
+            }
+            if let Some(max_source_pitch) = self.voice.max_source_pitch {
+                let _ = self.set_max_source_pitch(max_source_pitch);
+            }
+            if let Some(vq_num_neighbors) = self.voice.vq_num_neighbors {
+                let _ = Beatrice::set_vq_num_neighbors(self, vq_num_neighbors);
+            }
+        }
+    }
+
+    pub fn set_min_source_pitch(&mut self, min_source_pitch: f64) -> Result<(), BeatriceError> {
+        self.voice.set_min_source_pitch(min_source_pitch)?;
 
         unsafe {
             Beatrice20rc0_SetMinQuantizedPitch(
                 self.lib.pitch_context,
-                (((self.info.min_source_pitch - 33.0)
-                    * (BEATRICE_PITCH_BINS_PER_OCTAVE as f64 / 12.0)) as i32)
+                (((min_source_pitch - 33.0) * (BEATRICE_PITCH_BINS_PER_OCTAVE as f64 / 12.0))
+                    as i32)
                     .clamp(1, BEATRICE_20RC0_PITCH_BINS as i32 - 1),
             )
         };
+
+        Ok(())
     }
 
-    pub fn set_max_source_pitch(&mut self, max_source_pitch: f64) {
-        self.info.max_source_pitch = max_source_pitch.clamp(0.0, 128.0);
+    pub fn set_max_source_pitch(&mut self, max_source_pitch: f64) -> Result<(), BeatriceError> {
+        self.voice.set_max_source_pitch(max_source_pitch)?;
 
         unsafe {
             Beatrice20rc0_SetMaxQuantizedPitch(
                 self.lib.pitch_context,
-                (((self.info.max_source_pitch - 33.0)
-                    * (BEATRICE_PITCH_BINS_PER_OCTAVE as f64 / 12.0)) as i32)
+                (((max_source_pitch - 33.0) * (BEATRICE_PITCH_BINS_PER_OCTAVE as f64 / 12.0))
+                    as i32)
                     .clamp(1, BEATRICE_20RC0_PITCH_BINS as i32 - 1),
             )
         };
+
+        Ok(())
     }
 
     pub fn set_target_speaker(&mut self, speaker: u32) -> Result<(), BeatriceError> {
-        let new_target_speaker_id = speaker as i32;
+        self.set_speaker_mix(&[SpeakerWeight::new(speaker, 1.0)])
+    }
 
-        if (self.info.n_speakers + 1) <= new_target_speaker_id {
-            return Err(BeatriceError::SpeakerOutOfRange);
+    /// 埋め込みは重みで補間し、コードブックは一番重い話者のものを使う
+    pub fn set_speaker_mix(&mut self, speaker_mix: &[SpeakerWeight]) -> Result<(), BeatriceError> {
+        // 読み込む前は埋め込みが空
+        if self.model.is_none() {
+            return Err(BeatriceError::ModelNotLoaded);
         }
 
+        // 設定はモデルに埋め込みを渡してから変える
+        let speaker_mix = normalize_speaker_mix(speaker_mix, (self.voice.n_speakers + 1) as usize)?;
+
         // assert
         {
-            let n_speakers_plus1 = (self.info.n_speakers + 1) as usize;
+            let n_speakers_plus1 = (self.voice.n_speakers + 1) as usize;
 
             debug_assert!(
                 self.lib.codebooks.len()
@@ -331,9 +367,31 @@ impl BeatriceRC0 {
             );
         }
 
+        // 長さは変えないので、ライブラリに渡したポインタはずっと有効
+        self.lib
+            .mixed_additive_speaker_embedding
+            .resize(BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as usize, 0.0);
+        self.lib.mixed_key_value_speaker_embedding.resize(
+            (BEATRICE_20RC0_KV_LENGTH * BEATRICE_20RC0_KV_SPEAKER_EMBEDDING_CHANNELS) as usize,
+            0.0,
+        );
+
+        blend_embeddings(
+            &mut self.lib.mixed_additive_speaker_embedding,
+            &self.lib.additive_speaker_embeddings,
+            &speaker_mix,
+        );
+        blend_embeddings(
+            &mut self.lib.mixed_key_value_speaker_embedding,
+            &self.lib.key_value_speaker_embeddings,
+            &speaker_mix,
+        );
+
+        let dominant_speaker = speaker_mix[0].speaker;
+
         unsafe {
             {
-                let offset = new_target_speaker_id as usize
+                let offset = dominant_speaker as usize
                     * (BEATRICE_20RC0_CODEBOOK_SIZE * BEATRICE_20RC0_PHONE_CHANNELS) as usize;
 
                 Beatrice20rc0_SetCodebook(
@@ -342,59 +400,61 @@ impl BeatriceRC0 {
                 )
             }
 
-            {
-                let offset = new_target_speaker_id as usize
-                    * BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as usize;
-
-                Beatrice20rc0_SetAdditiveSpeakerEmbedding(
-                    self.lib.embedding_setter,
-                    self.lib.additive_speaker_embeddings.as_ptr().add(offset),
-                    self.lib.embedding_context,
-                    self.lib.waveform_context,
-                );
-            }
-
-            {
-                let offset = new_target_speaker_id as usize
-                    * (BEATRICE_20RC0_KV_LENGTH * BEATRICE_20RC0_KV_SPEAKER_EMBEDDING_CHANNELS)
-                        as usize;
+            Beatrice20rc0_SetAdditiveSpeakerEmbedding(
+                self.lib.embedding_setter,
+                self.lib.mixed_additive_speaker_embedding.as_ptr(),
+                self.lib.embedding_context,
+                self.lib.waveform_context,
+            );
 
-                Beatrice20rc0_RegisterKeyValueSpeakerEmbedding(
-                    self.lib.embedding_setter,
-                    self.lib.key_value_speaker_embeddings.as_ptr().add(offset),
-                    self.lib.embedding_context,
-                );
-            }
+            Beatrice20rc0_RegisterKeyValueSpeakerEmbedding(
+                self.lib.embedding_setter,
+                self.lib.mixed_key_value_speaker_embedding.as_ptr(),
+                self.lib.embedding_context,
+            );
         };
 
-        self.info.target_speaker = new_target_speaker_id;
         self.lib.key_value_speaker_embedding_set_count = 0;
+        self.voice.store_speaker_mix(speaker_mix);
 
         Ok(())
     }
 
-    pub fn set_formant_shift(&mut self, formant_shift: f64) {
-        self.info.formant_shift = formant_shift;
+    pub fn set_formant_shift(&mut self, formant_shift: f64) -> Result<(), BeatriceError> {
+        // 読み込む前は formant_shift_embeddings が空
+        if self.model.is_none() {
+            return Err(BeatriceError::ModelNotLoaded);
+        }
 
-        let index = (self.info.formant_shift * 2.0 + 4.0).round() as isize;
+        // 範囲外の値は端の埋め込みになってしまうので、ここで必ず弾く
+        self.voice.set_formant_shift(formant_shift)?;
 
-        debug_assert!((0..9).contains(&index));
         debug_assert!(
             self.lib.formant_shift_embeddings.len()
                 == 9 * BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as usize
         );
 
+        // 長さは変えないので、ライブラリに渡したポインタはずっと有効
+        self.lib
+            .interpolated_formant_shift_embedding
+            .resize(BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as usize, 0.0);
+
+        interpolate_formant_shift_embedding(
+            &mut self.lib.interpolated_formant_shift_embedding,
+            &self.lib.formant_shift_embeddings,
+            self.voice.formant_shift,
+        );
+
         unsafe {
             Beatrice20rc0_SetFormantShiftEmbedding(
                 self.lib.embedding_setter,
-                self.lib
-                    .formant_shift_embeddings
-                    .as_ptr()
-                    .offset(index * BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as isize),
+                self.lib.interpolated_formant_shift_embedding.as_ptr(),
                 self.lib.embedding_context,
                 self.lib.waveform_context,
             );
         }
+
+        Ok(())
     }
 
     fn set_key_value_speaker_embedding(&mut self) -> bool {
@@ -446,83 +506,9 @@ impl BeatriceRC0 {
             );
         }
 
-        const KPITCH_BINS_PER_SEMITONE: f64 = BEATRICE_PITCH_BINS_PER_OCTAVE as f64 / 12.0;
-
-        // PitchShift, IntonationIntensity
-        let mut tmp_quantized_pitch = self.info.average_source_pitch
-            + (quantized_pitch as f64 - self.info.average_source_pitch)
-                * self.info.intonation_intensity
-            + KPITCH_BINS_PER_SEMITONE * self.info.pitch_shift;
-
-        // PitchCorrection
-        if self.info.pitch_correction != 0.0 {
-            let before_pitch_correction = tmp_quantized_pitch;
-
-            match self.info.pitch_correction_type {
-                0 => {
-                    let nearest_pitch = ((tmp_quantized_pitch / KPITCH_BINS_PER_SEMITONE).floor()
-                        + 0.5)
-                        * KPITCH_BINS_PER_SEMITONE;
-
-                    let normalized_delta =
-                        (tmp_quantized_pitch - nearest_pitch) * (2.0 / KPITCH_BINS_PER_SEMITONE);
-
-                    if normalized_delta.abs() < 1e-4 {
-                        tmp_quantized_pitch = nearest_pitch
-                    } else {
-                        tmp_quantized_pitch = nearest_pitch
-                            + normalized_delta
-                                * (normalized_delta.abs().powf(-self.info.pitch_correction))
-                                * (KPITCH_BINS_PER_SEMITONE / 2.0);
-                    }
-
-                    debug_assert!(
-                        (tmp_quantized_pitch
-                            - (tmp_quantized_pitch / KPITCH_BINS_PER_SEMITONE).round()
-                                * KPITCH_BINS_PER_SEMITONE)
-                            .abs()
-                            <= (before_pitch_correction
-                                - (tmp_quantized_pitch / KPITCH_BINS_PER_SEMITONE).round()
-                                    * KPITCH_BINS_PER_SEMITONE)
-                                .abs()
-                                + 1e-4
-                    )
-                }
-
-                1 => {
-                    let nearest_pitch = (tmp_quantized_pitch / KPITCH_BINS_PER_SEMITONE).round()
-                        * KPITCH_BINS_PER_SEMITONE;
-
-                    let normalized_delta =
-                        (tmp_quantized_pitch - nearest_pitch) * (2.0 / KPITCH_BINS_PER_SEMITONE);
-
-                    if self.info.pitch_correction > (1.0 - 1e-4) {
-                        tmp_quantized_pitch = nearest_pitch;
-                    } else if normalized_delta >= 0.0 {
-                        tmp_quantized_pitch = nearest_pitch
-                            + normalized_delta.powf(1.0 / (1.0 - self.info.pitch_correction))
-                                * (KPITCH_BINS_PER_SEMITONE / 2.0);
-                    } else {
-                        tmp_quantized_pitch = nearest_pitch
-                            - (-normalized_delta).powf(1.0 / (1.0 - self.info.pitch_correction))
-                                * (KPITCH_BINS_PER_SEMITONE / 2.0);
-                    }
-                    debug_assert!(
-                        (tmp_quantized_pitch - nearest_pitch).abs()
-                            <= (before_pitch_correction - nearest_pitch).abs() + 1e-4
-                    );
-                }
-
-                _ => {
-                    debug_assert!(false);
-                }
-            }
-        }
-
-        quantized_pitch = {
-            let rounded = tmp_quantized_pitch.round() as i32;
-            rounded.clamp(1, BEATRICE_20RC0_PITCH_BINS as i32 - 1)
-        };
+        quantized_pitch =
+            self.voice
+                .process_pitch(input, quantized_pitch, BEATRICE_20RC0_PITCH_BINS);
 
         let mut output = [0.0; 240];
         unsafe {
@@ -563,162 +549,127 @@ impl Beatrice for BeatriceRC0 {
         self.infer(input)
     }
 
-    fn get_model_path(&self) -> Option<&Path> {
-        self.model.as_ref().map(|m| m.model_path.as_path())
+    fn flush(&mut self) -> Result<Vec<f32>, BeatriceError> {
+        let input = self.resamplers.flush_input();
+        self.infer(&input)
     }
 
-    fn get_n_speaker(&self) -> Option<i32> {
-        self.model.as_ref().map(|_| self.info.n_speakers)
+    fn get_latency(&self) -> Latency {
+        self.resamplers.latency()
     }
 
-    fn set_target_speaker(&mut self, speaker: u32) -> Result<(), BeatriceError> {
-        let new_target_speaker_id = speaker as i32;
-
-        if (self.info.n_speakers + 1) <= new_target_speaker_id {
-            return Err(BeatriceError::SpeakerOutOfRange);
-        }
-
-        // assert
-        {
-            let n_speakers_plus1 = (self.info.n_speakers + 1) as usize;
+    fn reset(&mut self) {
+        self.reset();
+    }
 
-            debug_assert!(
-                self.lib.codebooks.len()
-                    == n_speakers_plus1
-                        * (BEATRICE_20RC0_CODEBOOK_SIZE * BEATRICE_20RC0_PHONE_CHANNELS) as usize
-            );
+    fn set_channel_mix(&mut self, channel_mix: ChannelMix) -> Result<(), BeatriceError> {
+        self.resamplers.set_channel_mix(channel_mix)
+    }
 
-            debug_assert!(
-                self.lib.additive_speaker_embeddings.len()
-                    == n_speakers_plus1 * BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as usize
-            );
+    fn get_model_path(&self) -> Option<&Path> {
+        self.model.as_ref().map(|m| m.model_path.as_path())
+    }
 
-            debug_assert!(
-                self.lib.key_value_speaker_embeddings.len()
-                    == n_speakers_plus1
-                        * (BEATRICE_20RC0_KV_LENGTH * BEATRICE_20RC0_KV_SPEAKER_EMBEDDING_CHANNELS)
-                            as usize
-            );
-        }
+    fn get_n_speaker(&self) -> Option<i32> {
+        self.model.as_ref().map(|_| self.voice.n_speakers)
+    }
 
-        unsafe {
-            {
-                let offset = new_target_speaker_id as usize
-                    * (BEATRICE_20RC0_CODEBOOK_SIZE * BEATRICE_20RC0_PHONE_CHANNELS) as usize;
+    fn get_parameters(&self) -> VoiceParameters {
+        self.voice.parameters()
+    }
 
-                Beatrice20rc0_SetCodebook(
-                    self.lib.phone_context,
-                    self.lib.codebooks.as_ptr().add(offset),
-                )
-            }
+    fn get_capabilities(&self) -> Capabilities {
+        self.voice.capabilities()
+    }
 
-            {
-                let offset = new_target_speaker_id as usize
-                    * BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as usize;
+    fn start_pitch_calibration(&mut self, seconds: f64) -> Result<(), BeatriceError> {
+        self.voice.start_pitch_calibration(seconds)
+    }
 
-                Beatrice20rc0_SetAdditiveSpeakerEmbedding(
-                    self.lib.embedding_setter,
-                    self.lib.additive_speaker_embeddings.as_ptr().add(offset),
-                    self.lib.embedding_context,
-                    self.lib.waveform_context,
-                );
-            }
+    fn get_pitch_calibration(&self) -> CalibrationState {
+        self.voice.get_pitch_calibration()
+    }
 
-            {
-                let offset = new_target_speaker_id as usize
-                    * (BEATRICE_20RC0_KV_LENGTH * BEATRICE_20RC0_KV_SPEAKER_EMBEDDING_CHANNELS)
-                        as usize;
+    fn finish_pitch_calibration(&mut self) -> Option<PitchCalibration> {
+        self.voice.finish_pitch_calibration()
+    }
 
-                Beatrice20rc0_RegisterKeyValueSpeakerEmbedding(
-                    self.lib.embedding_setter,
-                    self.lib.key_value_speaker_embeddings.as_ptr().add(offset),
-                    self.lib.embedding_context,
-                );
-            }
-        };
+    fn set_pitch_telemetry(&mut self, enabled: bool) {
+        self.voice.set_pitch_telemetry(enabled);
+    }
 
-        self.info.target_speaker = new_target_speaker_id;
-        self.lib.key_value_speaker_embedding_set_count = 0;
+    fn take_pitch_telemetry(&mut self) -> Vec<PitchTelemetry> {
+        self.voice.take_pitch_telemetry()
+    }
 
-        Ok(())
+    fn set_target_speaker(&mut self, speaker: u32) -> Result<(), BeatriceError> {
+        self.set_target_speaker(speaker)
     }
 
-    fn set_formant_shift(&mut self, formant_shift: f64) {
-        self.info.formant_shift = formant_shift;
+    fn set_speaker_mix(&mut self, speaker_mix: &[SpeakerWeight]) -> Result<(), BeatriceError> {
+        self.set_speaker_mix(speaker_mix)
+    }
 
-        let index = (self.info.formant_shift * 2.0 + 4.0).round() as isize;
+    fn set_formant_shift(&mut self, formant_shift: f64) -> Result<(), BeatriceError> {
+        self.set_formant_shift(formant_shift)
+    }
 
-        debug_assert!((0..9).contains(&index));
-        debug_assert!(
-            self.lib.formant_shift_embeddings.len()
-                == 9 * BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as usize
-        );
+    fn set_pitch_shift(&mut self, pitch_shift: f64) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_shift(pitch_shift)
+    }
 
-        unsafe {
-            Beatrice20rc0_SetFormantShiftEmbedding(
-                self.lib.embedding_setter,
-                self.lib
-                    .formant_shift_embeddings
-                    .as_ptr()
-                    .offset(index * BEATRICE_WAVEFORM_GENERATOR_HIDDEN_CHANNELS as isize),
-                self.lib.embedding_context,
-                self.lib.waveform_context,
-            );
-        }
+    fn set_auto_pitch_shift(&mut self, auto_pitch_shift: bool) -> Result<(), BeatriceError> {
+        self.voice.set_auto_pitch_shift(auto_pitch_shift);
+        Ok(())
     }
 
-    fn set_pitch_shift(&mut self, pitch_shift: f64) {
-        self.info.pitch_shift = pitch_shift;
+    fn set_average_source_pitch(&mut self, average_source_pitch: f64) -> Result<(), BeatriceError> {
+        self.voice.set_average_source_pitch(average_source_pitch)
     }
 
-    fn set_average_source_pitch(&mut self, average_source_pitch: f64) {
-        self.info.average_source_pitch = average_source_pitch;
+    fn set_intonation_intensity(&mut self, intonation_intensity: f64) -> Result<(), BeatriceError> {
+        self.voice.set_intonation_intensity(intonation_intensity)
     }
 
-    fn set_intonation_intensity(&mut self, intonation_intensity: f64) {
-        self.info.intonation_intensity = intonation_intensity;
+    fn set_pitch_correction(&mut self, pitch_correction: f64) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_correction(pitch_correction)
     }
 
-    fn set_pitch_correction(&mut self, pitch_correction: f64) {
-        self.info.pitch_correction = pitch_correction;
+    fn set_pitch_correction_mode(
+        &mut self,
+        pitch_correction_mode: PitchCorrectionMode,
+    ) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_correction_mode(pitch_correction_mode)
     }
 
-    fn set_pitch_correction_type(&mut self, pitch_correction_type: i32) {
-        self.info.pitch_correction_type = pitch_correction_type;
+    fn set_pitch_glide_time(&mut self, pitch_glide_time: f64) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_glide_time(pitch_glide_time)
     }
 
-    fn set_min_source_pitch(&mut self, min_source_pitch: f64) {
-        self.info.min_source_pitch = min_source_pitch.clamp(0.0, 128.0);
+    fn set_pitch_median_window(&mut self, pitch_median_window: u32) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_median_window(pitch_median_window)
+    }
 
-        unsafe {
-            Beatrice20rc0_SetMinQuantizedPitch(
-                self.lib.pitch_context,
-                (((self.info.min_source_pitch - 33.0)
-                    * (BEATRICE_PITCH_BINS_PER_OCTAVE as f64 / 12.0)) as i32)
-                    .clamp(1, BEATRICE_20RC0_PITCH_BINS as i32 - 1),
-            )
-        };
+    fn set_pitch_hysteresis(&mut self, pitch_hysteresis: f64) -> Result<(), BeatriceError> {
+        self.voice.set_pitch_hysteresis(pitch_hysteresis)
     }
 
-    fn set_max_source_pitch(&mut self, max_source_pitch: f64) {
-        self.info.max_source_pitch = max_source_pitch.clamp(0.0, 128.0);
+    fn set_min_source_pitch(&mut self, min_source_pitch: f64) -> Result<(), BeatriceError> {
+        self.set_min_source_pitch(min_source_pitch)
+    }
 
-        unsafe {
-            Beatrice20rc0_SetMaxQuantizedPitch(
-                self.lib.pitch_context,
-                (((self.info.max_source_pitch - 33.0)
-                    * (BEATRICE_PITCH_BINS_PER_OCTAVE as f64 / 12.0)) as i32)
-                    .clamp(1, BEATRICE_20RC0_PITCH_BINS as i32 - 1),
-            )
-        };
+    fn set_max_source_pitch(&mut self, max_source_pitch: f64) -> Result<(), BeatriceError> {
+        self.set_max_source_pitch(max_source_pitch)
     }
 
-    fn set_vq_num_neighbors(&mut self, vq_num_neighbors: i32) {
-        self.info.vq_num_neighbors = vq_num_neighbors.clamp(0, 8);
+    fn set_vq_num_neighbors(&mut self, vq_num_neighbors: i32) -> Result<(), BeatriceError> {
+        self.voice.set_vq_num_neighbors(vq_num_neighbors)?;
 
         unsafe {
-            Beatrice20rc0_SetVQNumNeighbors(self.lib.phone_context, self.info.vq_num_neighbors);
+            Beatrice20rc0_SetVQNumNeighbors(self.lib.phone_context, vq_num_neighbors);
         }
+
+        Ok(())
     }
 
     fn get_model_version(&self) -> &'static str {
@@ -1,5 +1,8 @@
 use serde::Deserialize;
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug, Deserialize)]
 pub struct BeatriceToml {
@@ -14,6 +17,48 @@ impl BeatriceToml {
 
         Ok(parsed)
     }
+
+    /// 話者 ID ごとの `Voice::average_pitch`
+    pub fn voice_average_pitches(&self) -> HashMap<u32, f64> {
+        self.voice
+            .iter()
+            .map(|(&id, voice)| (id, voice.average_pitch))
+            .collect()
+    }
+}
+
+/// モデルフォルダ内の toml を探す
+pub(crate) fn find_toml_path(model_folder: &Path) -> std::io::Result<Option<PathBuf>> {
+    for f in std::fs::read_dir(model_folder)?.flatten() {
+        let path = f.path();
+        if !path.is_file() {
+            continue;
+        }
+
+        let Some(ext) = path.extension() else {
+            continue;
+        };
+
+        if ext == "toml" {
+            return Ok(Some(path));
+        }
+    }
+
+    Ok(None)
+}
+
+/// モデルフォルダの toml から話者ごとの平均ピッチを読む。toml が無い・読めない場合は空
+#[cfg_attr(not(feature = "native"), allow(dead_code))]
+pub(crate) fn load_voice_average_pitches(model_folder: &Path) -> HashMap<u32, f64> {
+    let Some(toml_path) = find_toml_path(model_folder).ok().flatten() else {
+        return HashMap::new();
+    };
+
+    let Ok(beatrice_toml) = BeatriceToml::load_from_tomlpath(toml_path) else {
+        return HashMap::new();
+    };
+
+    beatrice_toml.voice_average_pitches()
 }
 
 #[derive(Debug, Deserialize)]
@@ -0,0 +1,261 @@
+use std::path::PathBuf;
+
+use beatrice_lib::{PitchCorrectionMode, Scale, ScaleCorrection, SpeakerWeight};
+use clap::{Parser, ValueEnum};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum PitchCorrectionModeArg {
+    Off,
+    Push,
+    Pull,
+    Scale,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ScaleArg {
+    Major,
+    Minor,
+    MajorPentatonic,
+    MinorPentatonic,
+}
+
+/// "話者:重み" (例: 0:0.7)
+fn parse_speaker_weight(s: &str) -> Result<SpeakerWeight, String> {
+    let (speaker, weight) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected SPEAKER:WEIGHT, got {s}"))?;
+
+    Ok(SpeakerWeight::new(
+        speaker.trim().parse().map_err(|err| format!("{err}"))?,
+        weight.trim().parse().map_err(|err| format!("{err}"))?,
+    ))
+}
+
+/// Beatrice のモデルで WAV ファイルを変換する
+#[derive(Debug, Parser)]
+#[command(name = "beatrice-convert")]
+struct Args {
+    /// モデルフォルダ
+    #[arg(short, long)]
+    model: PathBuf,
+
+    /// 入力 WAV ファイル
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// 出力 WAV ファイル
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// 出力のサンプリングレート
+    #[arg(long, default_value_t = 48000)]
+    sample_rate: u32,
+
+    /// 変換の前に入力の声から average/min/max source pitch を求めて使う。個別の指定があればそちらを優先する
+    #[arg(long)]
+    calibrate: bool,
+
+    #[arg(long)]
+    target_speaker: Option<u32>,
+
+    /// 話者を混ぜる (例: 0:0.7,1:0.3)。指定すると --target-speaker より優先する
+    #[arg(long, value_delimiter = ',', value_parser = parse_speaker_weight)]
+    speaker_mix: Option<Vec<SpeakerWeight>>,
+
+    #[arg(long, allow_hyphen_values = true)]
+    pitch_shift: Option<f64>,
+
+    /// ターゲットの声の平均ピッチと average source pitch の差をピッチシフトにする。--pitch-shift があればそちらを優先する
+    #[arg(long)]
+    auto_pitch_shift: bool,
+
+    #[arg(long, allow_hyphen_values = true)]
+    formant_shift: Option<f64>,
+
+    /// 入力の声の平均の音高 (MIDI ノート番号、例: 52 = E3)
+    #[arg(long)]
+    average_source_pitch: Option<f64>,
+
+    #[arg(long)]
+    intonation_intensity: Option<f64>,
+
+    #[arg(long)]
+    pitch_correction: Option<f64>,
+
+    #[arg(long, value_enum)]
+    pitch_correction_mode: Option<PitchCorrectionModeArg>,
+
+    /// スケール補正の主音 (0 = C, 1 = C#, ..., 11 = B)
+    #[arg(long, default_value_t = 0)]
+    key: u32,
+
+    #[arg(long, value_enum, default_value = "major")]
+    scale: ScaleArg,
+
+    /// 主音から数えた半音単位 (0〜11) でスケールの音を指定する (例: 0,2,4,7,9)。指定すると --scale より優先する
+    #[arg(long, value_delimiter = ',', value_parser = clap::value_parser!(u32).range(0..12))]
+    scale_notes: Option<Vec<u32>>,
+
+    /// スケール補正の A4 の周波数 (Hz)
+    #[arg(long, default_value_t = 440.0)]
+    reference_frequency: f64,
+
+    /// ピッチが目標に近づくまでの時定数 (ms)
+    #[arg(long)]
+    pitch_glide_time: Option<f64>,
+
+    /// ピッチのメディアンフィルタの幅 (hop 数)
+    #[arg(long)]
+    pitch_median_window: Option<u32>,
+
+    /// ピッチを動かさない変化の幅 (半音)
+    #[arg(long)]
+    pitch_hysteresis: Option<f64>,
+
+    #[arg(long)]
+    min_source_pitch: Option<f64>,
+
+    #[arg(long)]
+    max_source_pitch: Option<f64>,
+
+    #[arg(long)]
+    vq_num_neighbors: Option<i32>,
+}
+
+impl Args {
+    fn pitch_correction_mode(&self) -> Option<PitchCorrectionMode> {
+        let mode = match self.pitch_correction_mode? {
+            PitchCorrectionModeArg::Off => PitchCorrectionMode::Off,
+            PitchCorrectionModeArg::Push => PitchCorrectionMode::Push,
+            PitchCorrectionModeArg::Pull => PitchCorrectionMode::Pull,
+            PitchCorrectionModeArg::Scale => {
+                let scale = match (&self.scale_notes, self.scale) {
+                    (Some(degrees), _) => {
+                        let mut notes = [false; 12];
+                        for &degree in degrees {
+                            notes[degree as usize] = true;
+                        }
+                        Scale::Custom(notes)
+                    }
+                    (None, ScaleArg::Major) => Scale::Major,
+                    (None, ScaleArg::Minor) => Scale::Minor,
+                    (None, ScaleArg::MajorPentatonic) => Scale::MajorPentatonic,
+                    (None, ScaleArg::MinorPentatonic) => Scale::MinorPentatonic,
+                };
+
+                PitchCorrectionMode::Scale(ScaleCorrection {
+                    key: self.key,
+                    scale,
+                    reference_frequency: self.reference_frequency,
+                })
+            }
+        };
+
+        Some(mode)
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let calibration = if args.calibrate {
+        let calibration = beatrice_lib::calibrate_wav(
+            &args.input,
+            |in_sample_rate, out_sample_rate, in_channel, out_channel| {
+                beatrice_lib::new(
+                    &args.model,
+                    in_sample_rate,
+                    out_sample_rate,
+                    in_channel,
+                    out_channel,
+                )
+            },
+        )?;
+
+        eprintln!(
+            "average_source_pitch: {}, min_source_pitch: {:?}, max_source_pitch: {:?}",
+            calibration.average_source_pitch,
+            calibration.min_source_pitch,
+            calibration.max_source_pitch,
+        );
+
+        Some(calibration)
+    } else {
+        None
+    };
+
+    beatrice_lib::convert_wav(
+        &args.input,
+        &args.output,
+        args.sample_rate,
+        |in_sample_rate, out_sample_rate, in_channel, out_channel| {
+            let mut beatrice = beatrice_lib::new(
+                &args.model,
+                in_sample_rate,
+                out_sample_rate,
+                in_channel,
+                out_channel,
+            )?;
+
+            if let Some(calibration) = calibration {
+                beatrice.set_average_source_pitch(calibration.average_source_pitch)?;
+                if let Some(v) = calibration.min_source_pitch {
+                    beatrice.set_min_source_pitch(v)?;
+                }
+                if let Some(v) = calibration.max_source_pitch {
+                    beatrice.set_max_source_pitch(v)?;
+                }
+            }
+
+            if let Some(v) = args.target_speaker {
+                beatrice.set_target_speaker(v)?;
+            }
+            if let Some(v) = &args.speaker_mix {
+                beatrice.set_speaker_mix(v)?;
+            }
+            if let Some(v) = args.average_source_pitch {
+                beatrice.set_average_source_pitch(v)?;
+            }
+            if args.auto_pitch_shift {
+                beatrice.set_auto_pitch_shift(true)?;
+            }
+            if let Some(v) = args.pitch_shift {
+                beatrice.set_pitch_shift(v)?;
+            }
+            if let Some(v) = args.formant_shift {
+                beatrice.set_formant_shift(v)?;
+            }
+            if let Some(v) = args.intonation_intensity {
+                beatrice.set_intonation_intensity(v)?;
+            }
+            if let Some(v) = args.pitch_correction {
+                beatrice.set_pitch_correction(v)?;
+            }
+            if let Some(v) = args.pitch_correction_mode() {
+                beatrice.set_pitch_correction_mode(v)?;
+            }
+            if let Some(v) = args.pitch_glide_time {
+                beatrice.set_pitch_glide_time(v)?;
+            }
+            if let Some(v) = args.pitch_median_window {
+                beatrice.set_pitch_median_window(v)?;
+            }
+            if let Some(v) = args.pitch_hysteresis {
+                beatrice.set_pitch_hysteresis(v)?;
+            }
+            if let Some(v) = args.min_source_pitch {
+                beatrice.set_min_source_pitch(v)?;
+            }
+            if let Some(v) = args.max_source_pitch {
+                beatrice.set_max_source_pitch(v)?;
+            }
+            if let Some(v) = args.vq_num_neighbors {
+                beatrice.set_vq_num_neighbors(v)?;
+            }
+
+            Ok(beatrice)
+        },
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::{BEATRICE_IN_HOP_LENGTH, BEATRICE_IN_SAMPLE_RATE},
+    errors::BeatriceError,
+    parameters::{Capabilities, ParameterRange},
+};
+
+const HOPS_PER_SECOND: f64 = BEATRICE_IN_SAMPLE_RATE as f64 / BEATRICE_IN_HOP_LENGTH as f64;
+
+// これより小さい hop は無声として数えない (-40dBFS)
+const VOICED_RMS_THRESHOLD: f32 = 0.01;
+
+// min/max source pitch の候補は、外れ値を除いた範囲からこれだけ (半音) 広げる
+const SOURCE_PITCH_MARGIN: f64 = 3.0;
+
+/// 入力の声から求めた音高 (いずれも MIDI ノート番号)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PitchCalibration {
+    /// `set_average_source_pitch` に使う値
+    pub average_source_pitch: f64,
+    /// `set_min_source_pitch` に使う値。対応していないモデルでは `None`
+    pub min_source_pitch: Option<f64>,
+    /// `set_max_source_pitch` に使う値。対応していないモデルでは `None`
+    pub max_source_pitch: Option<f64>,
+    /// 集計に使った有声の hop の数
+    pub voiced_hops: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum CalibrationState {
+    #[default]
+    Idle,
+    /// `progress` は 0.0 から 1.0
+    Running {
+        progress: f64,
+    },
+    Finished(PitchCalibration),
+}
+
+/// `EstimatePitch1` の結果を集めて平均の音高を求める
+#[cfg_attr(not(any(feature = "native", feature = "mock")), allow(dead_code))]
+#[derive(Debug, Default)]
+pub(crate) struct PitchCalibrator {
+    target_hops: usize,
+    // 有声の hop の音高 (MIDI ノート番号)
+    notes: Vec<f64>,
+    state: CalibrationState,
+}
+
+#[cfg_attr(not(any(feature = "native", feature = "mock")), allow(dead_code))]
+impl PitchCalibrator {
+    /// 有声の入力を `seconds` 秒分集め始める。集めていた分は捨てる
+    pub fn start(&mut self, seconds: f64) -> Result<(), BeatriceError> {
+        if !(seconds.is_finite() && 0.0 < seconds) {
+            return Err(BeatriceError::ParameterOutOfRange(
+                "calibration_seconds",
+                seconds,
+            ));
+        }
+
+        self.target_hops = (seconds * HOPS_PER_SECOND).ceil() as usize;
+        self.notes.clear();
+        self.notes.reserve(self.target_hops);
+        self.state = CalibrationState::Running { progress: 0.0 };
+
+        Ok(())
+    }
+
+    pub fn state(&self) -> CalibrationState {
+        self.state
+    }
+
+    /// 1 hop 分の入力と、そこから推定した音高 (MIDI ノート番号) を渡す
+    ///
+    /// 無音に近い hop と音高が推定できなかった (`note` が `None`) hop は数えない
+    pub fn push(&mut self, input: &[f32], note: Option<f64>, capabilities: &Capabilities) {
+        if !matches!(self.state, CalibrationState::Running { .. }) {
+            return;
+        }
+
+        let Some(note) = note else {
+            return;
+        };
+
+        let rms = (input.iter().map(|v| v * v).sum::<f32>() / input.len() as f32).sqrt();
+        if rms < VOICED_RMS_THRESHOLD {
+            return;
+        }
+
+        self.notes.push(note);
+
+        self.state = if self.target_hops <= self.notes.len() {
+            match self.calculate(capabilities) {
+                Some(calibration) => CalibrationState::Finished(calibration),
+                None => CalibrationState::Idle,
+            }
+        } else {
+            CalibrationState::Running {
+                progress: self.notes.len() as f64 / self.target_hops as f64,
+            }
+        };
+    }
+
+    /// 集めるのを止め、それまでの分で結果を返す。有声の hop が 1 つも無ければ `None`
+    pub fn finish(&mut self, capabilities: &Capabilities) -> Option<PitchCalibration> {
+        let calibration = match self.state {
+            CalibrationState::Finished(calibration) => Some(calibration),
+            _ => self.calculate(capabilities),
+        };
+
+        self.notes.clear();
+        self.state = CalibrationState::Idle;
+
+        calibration
+    }
+
+    fn calculate(&self, capabilities: &Capabilities) -> Option<PitchCalibration> {
+        if self.notes.is_empty() {
+            return None;
+        }
+
+        let mut notes = self.notes.clone();
+        notes.sort_by(f64::total_cmp);
+
+        let percentile = |p: f64| notes[((notes.len() - 1) as f64 * p).round() as usize];
+        let fit = |range: ParameterRange, v: f64| {
+            ((v / range.step).round() * range.step).clamp(range.min, range.max)
+        };
+
+        let average = notes.iter().sum::<f64>() / notes.len() as f64;
+
+        Some(PitchCalibration {
+            average_source_pitch: fit(capabilities.average_source_pitch, average),
+            min_source_pitch: capabilities
+                .min_source_pitch
+                .map(|range| fit(range, percentile(0.05) - SOURCE_PITCH_MARGIN)),
+            max_source_pitch: capabilities
+                .max_source_pitch
+                .map(|range| fit(range, percentile(0.95) + SOURCE_PITCH_MARGIN)),
+            voiced_hops: notes.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Capabilities,
+        calibration::{CalibrationState, PitchCalibrator},
+    };
+
+    #[test]
+    fn test_calibrator() {
+        let mut calibrator = PitchCalibrator::default();
+        assert!(calibrator.start(0.0).is_err());
+        calibrator.start(0.1).unwrap();
+
+        // 無音と推定できなかった hop は数えない
+        calibrator.push(&[0.0; 160], Some(40.0), &Capabilities::RC0);
+        calibrator.push(&[0.5; 160], None, &Capabilities::RC0);
+        assert_eq!(
+            calibrator.state(),
+            CalibrationState::Running { progress: 0.0 }
+        );
+
+        for i in 0..10 {
+            calibrator.push(
+                &[0.5; 160],
+                Some(55.0 + (i % 2) as f64 * 2.0),
+                &Capabilities::RC0,
+            );
+        }
+
+        let CalibrationState::Finished(calibration) = calibrator.state() else {
+            panic!("calibration is not finished");
+        };
+        assert_eq!(calibration.voiced_hops, 10);
+        assert_eq!(calibration.average_source_pitch, 56.0);
+        assert_eq!(calibration.min_source_pitch, Some(52.0));
+        assert_eq!(calibration.max_source_pitch, Some(60.0));
+
+        // RC.0 より前のモデルでは min/max は無い
+        calibrator.start(10.0).unwrap();
+        calibrator.push(&[0.5; 160], Some(60.0), &Capabilities::BETA);
+        let calibration = calibrator.finish(&Capabilities::BETA).unwrap();
+        assert_eq!(calibration.average_source_pitch, 60.0);
+        assert_eq!(calibration.min_source_pitch, None);
+        assert_eq!(calibrator.state(), CalibrationState::Idle);
+    }
+}
@@ -0,0 +1,8 @@
+// bindings.rs のうちモデルのバージョンに依存しない定数
+// native feature が無効でも使えるようにここにも置いておく
+
+pub const BEATRICE_IN_HOP_LENGTH: u32 = 160;
+pub const BEATRICE_OUT_HOP_LENGTH: u32 = 240;
+pub const BEATRICE_IN_SAMPLE_RATE: u32 = 16000;
+pub const BEATRICE_OUT_SAMPLE_RATE: u32 = 24000;
+pub const BEATRICE_PITCH_BINS_PER_OCTAVE: u32 = 96;
@@ -1,6 +1,7 @@
 #![allow(non_upper_case_globals)]
 use std::ffi::NulError;
 
+#[cfg(feature = "native")]
 use crate::bindings::*;
 
 #[derive(Debug, thiserror::Error)]
@@ -23,6 +24,27 @@ pub enum BeatriceError {
     #[error("InvalidFileSize")]
     InvalidFileSize,
 
+    #[error("UnsupportedChannelLayout: {0} channels")]
+    UnsupportedChannelLayout(u32),
+
+    #[error("InvalidChannelIndex: {0}")]
+    InvalidChannelIndex(u32),
+
+    #[error("InvalidSampleRate: {0}")]
+    InvalidSampleRate(f64),
+
+    #[error("ParameterOutOfRange: {0} = {1}")]
+    ParameterOutOfRange(&'static str, f64),
+
+    #[error("EmptyScale")]
+    EmptyScale,
+
+    #[error("NoVoicedInput")]
+    NoVoicedInput,
+
+    #[error("UnsupportedParameter: {0}")]
+    UnsupportedParameter(&'static str),
+
     #[error("IO Error: {0}")]
     IOError(#[from] std::io::Error),
 
@@ -31,8 +53,18 @@ pub enum BeatriceError {
 
     #[error("NulError: {0}")]
     NulError(#[from] NulError),
+
+    #[error("wav Error: {0}")]
+    WavError(#[from] hound::Error),
+
+    #[error("resampler construction Error: {0}")]
+    ResamplerConstructionError(#[from] rubato::ResamplerConstructionError),
+
+    #[error("resample Error: {0}")]
+    ResampleError(#[from] rubato::ResampleError),
 }
 
+#[cfg(feature = "native")]
 impl TryFrom<Beatrice_ErrorCode> for BeatriceError {
     type Error = ();
 
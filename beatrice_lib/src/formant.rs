@@ -0,0 +1,56 @@
+// formant_shift_embeddings は -2.0 から 2.0 まで 0.5 刻みの 9 行
+const FORMANT_SHIFT_EMBEDDINGS: usize = 9;
+const FORMANT_SHIFT_ROWS_PER_SEMITONE: f64 = 2.0;
+
+/// `formant_shift` を挟む 2 行を線形補間して `output` に書く
+///
+/// 各行は `output.len()` の長さで、範囲外の `formant_shift` は端の行になる
+#[cfg_attr(not(feature = "native"), allow(dead_code))]
+pub(crate) fn interpolate_formant_shift_embedding(
+    output: &mut [f32],
+    formant_shift_embeddings: &[f32],
+    formant_shift: f64,
+) {
+    let channels = output.len();
+    debug_assert!(formant_shift_embeddings.len() == FORMANT_SHIFT_EMBEDDINGS * channels);
+
+    let last = (FORMANT_SHIFT_EMBEDDINGS - 1) as f64;
+    let position = (formant_shift * FORMANT_SHIFT_ROWS_PER_SEMITONE + last / 2.0).clamp(0.0, last);
+
+    let lower = (position.floor() as usize).min(FORMANT_SHIFT_EMBEDDINGS - 2);
+    let frac = (position - lower as f64) as f32;
+
+    let lower_row = &formant_shift_embeddings[lower * channels..(lower + 1) * channels];
+    let upper_row = &formant_shift_embeddings[(lower + 1) * channels..(lower + 2) * channels];
+
+    for ((out, &a), &b) in output.iter_mut().zip(lower_row).zip(upper_row) {
+        *out = a + (b - a) * frac;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formant::interpolate_formant_shift_embedding;
+
+    #[test]
+    fn test_interpolate_formant_shift_embedding() {
+        // i 行目は [i, 10 * i]
+        let embeddings = (0..9)
+            .flat_map(|i| [i as f32, 10.0 * i as f32])
+            .collect::<Vec<_>>();
+        let mut output = [0.0; 2];
+
+        // 0.5 刻みの値は今までと同じ行になる
+        interpolate_formant_shift_embedding(&mut output, &embeddings, 0.0);
+        assert_eq!(output, [4.0, 40.0]);
+        interpolate_formant_shift_embedding(&mut output, &embeddings, 2.0);
+        assert_eq!(output, [8.0, 80.0]);
+        interpolate_formant_shift_embedding(&mut output, &embeddings, -2.0);
+        assert_eq!(output, [0.0, 0.0]);
+
+        interpolate_formant_shift_embedding(&mut output, &embeddings, 0.25);
+        assert_eq!(output, [4.5, 45.0]);
+        interpolate_formant_shift_embedding(&mut output, &embeddings, -1.125);
+        assert_eq!(output, [1.75, 17.5]);
+    }
+}
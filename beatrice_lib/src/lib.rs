@@ -1,15 +1,48 @@
 mod beatrice;
+#[cfg(feature = "native")]
 mod beatrice_beta_0;
+#[cfg(feature = "native")]
 mod beatrice_beta_1;
+#[cfg(feature = "mock")]
+mod beatrice_mock;
+#[cfg(feature = "native")]
 mod beatrice_rc_0;
 mod beatrice_toml;
+#[cfg(feature = "native")]
 mod bindings;
+mod calibration;
+mod constants;
 mod errors;
+mod formant;
+mod offline;
+mod parameters;
+#[cfg(any(feature = "native", feature = "mock", test))]
+mod pitch;
 mod resampler;
+mod speaker_mix;
+mod telemetry;
+#[cfg(any(feature = "native", feature = "mock"))]
+mod voice;
 
-pub use beatrice::{Beatrice, new};
+pub use beatrice::{Beatrice, Latency, new};
+#[cfg(feature = "native")]
 pub use beatrice_beta_0::BeatriceBeta0;
+#[cfg(feature = "native")]
 pub use beatrice_beta_1::BeatriceBeta1;
+#[cfg(feature = "mock")]
+pub use beatrice_mock::MockBeatrice;
+#[cfg(feature = "native")]
 pub use beatrice_rc_0::BeatriceRC0;
 pub use beatrice_toml::{BeatriceToml, ModelInfo, Portrait, Voice};
+pub use calibration::{CalibrationState, PitchCalibration};
+pub use constants::{
+    BEATRICE_IN_HOP_LENGTH, BEATRICE_OUT_HOP_LENGTH, BEATRICE_PITCH_BINS_PER_OCTAVE,
+};
 pub use errors::BeatriceError;
+pub use offline::{calibrate_wav, convert_samples, convert_wav};
+pub use parameters::{
+    Capabilities, ParameterRange, PitchCorrectionMode, Scale, ScaleCorrection, VoiceParameters,
+};
+pub use resampler::{BeatriceResampler, ChannelMix, Downmix, Upmix};
+pub use speaker_mix::SpeakerWeight;
+pub use telemetry::PitchTelemetry;
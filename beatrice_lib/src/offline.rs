@@ -0,0 +1,236 @@
+use std::path::Path;
+
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+
+use crate::{beatrice::Beatrice, calibration::PitchCalibration, errors::BeatriceError};
+
+/// WAV ファイルを変換して書き出す
+///
+/// `create_beatrice` には入力ファイルのサンプリングレートとチャンネル数、出力のサンプリングレートと
+/// チャンネル数 (常にモノラル) が `beatrice_lib::new` と同じ順番で渡される
+pub fn convert_wav(
+    input_path: impl AsRef<Path>,
+    output_path: impl AsRef<Path>,
+    out_sample_rate: u32,
+    create_beatrice: impl FnOnce(f64, f64, u32, u32) -> Result<Box<dyn Beatrice>, BeatriceError>,
+) -> Result<(), BeatriceError> {
+    let (in_spec, input) = read_wav(input_path)?;
+
+    let mut beatrice = create_beatrice(
+        in_spec.sample_rate.into(),
+        out_sample_rate.into(),
+        in_spec.channels.into(),
+        1,
+    )?;
+
+    let output = convert_samples(
+        beatrice.as_mut(),
+        &input,
+        in_spec.sample_rate,
+        in_spec.channels,
+        out_sample_rate,
+        1,
+    )?;
+
+    let out_spec = WavSpec {
+        channels: 1,
+        sample_rate: out_sample_rate,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+
+    let mut writer = WavWriter::create(output_path, out_spec)?;
+    for s in output {
+        writer.write_sample(s)?;
+    }
+    writer.finalize()?;
+
+    Ok(())
+}
+
+/// WAV ファイルの声から `average_source_pitch` などの値を求める
+///
+/// `create_beatrice` の引数は `convert_wav` と同じ。出力は捨てる
+pub fn calibrate_wav(
+    input_path: impl AsRef<Path>,
+    create_beatrice: impl FnOnce(f64, f64, u32, u32) -> Result<Box<dyn Beatrice>, BeatriceError>,
+) -> Result<PitchCalibration, BeatriceError> {
+    let (in_spec, input) = read_wav(input_path)?;
+
+    let mut beatrice = create_beatrice(
+        in_spec.sample_rate.into(),
+        in_spec.sample_rate.into(),
+        in_spec.channels.into(),
+        1,
+    )?;
+
+    let seconds = input.len() as f64 / in_spec.channels as f64 / in_spec.sample_rate as f64;
+    beatrice.start_pitch_calibration(seconds)?;
+
+    let block_frames = (in_spec.sample_rate as f64 / 100.0).round() as usize;
+    for block in input.chunks(block_frames * in_spec.channels as usize) {
+        beatrice.infer(block)?;
+    }
+
+    beatrice
+        .finish_pitch_calibration()
+        .ok_or(BeatriceError::NoVoicedInput)
+}
+
+/// インターリーブされた音声を 10ms ずつ `Beatrice::infer` に流し、最後に `Beatrice::flush` で出し切る
+///
+/// 遅延は先読みとして現れるだけで出力の先頭に無音は入らないので、入力と同じ長さに切り揃えれば時間が合う
+pub fn convert_samples(
+    beatrice: &mut dyn Beatrice,
+    input: &[f32],
+    in_sample_rate: u32,
+    in_channel: u16,
+    out_sample_rate: u32,
+    out_channel: u16,
+) -> Result<Vec<f32>, BeatriceError> {
+    let in_channel = in_channel as usize;
+    let out_channel = out_channel as usize;
+
+    let block_frames = (in_sample_rate as f64 / 100.0).round() as usize;
+
+    let mut output = vec![];
+    for block in input.chunks(block_frames * in_channel) {
+        output.extend(beatrice.infer(block)?);
+    }
+    output.extend(beatrice.flush()?);
+
+    let in_frames = input.len() / in_channel;
+    let out_frames =
+        (in_frames as f64 * out_sample_rate as f64 / in_sample_rate as f64).round() as usize;
+
+    output.resize(out_frames * out_channel, 0.0);
+
+    Ok(output)
+}
+
+fn read_wav(path: impl AsRef<Path>) -> Result<(WavSpec, Vec<f32>), BeatriceError> {
+    let mut reader = WavReader::open(path)?;
+    let spec = reader.spec();
+
+    let samples = match spec.sample_format {
+        SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?,
+        SampleFormat::Int => {
+            let scale = 1.0 / (1_i64 << (spec.bits_per_sample - 1)) as f32;
+
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 * scale))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+
+    Ok((spec, samples))
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+
+    use crate::{
+        Beatrice, MockBeatrice,
+        offline::{calibrate_wav, convert_samples, convert_wav},
+    };
+
+    #[test]
+    fn test_convert_wav() {
+        let dir = std::env::temp_dir().join("beatrice_lib_test_convert_wav");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let input_path = dir.join("input.wav");
+        let output_path = dir.join("output.wav");
+
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+
+        let mut writer = WavWriter::create(&input_path, spec).unwrap();
+        for i in 0..44100 {
+            let s = ((i as f32 * 0.05).sin() * 10000.0) as i16;
+            writer.write_sample(s).unwrap();
+            writer.write_sample(s).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        convert_wav(&input_path, &output_path, 48000, |ir, or, ic, oc| {
+            let mut beatrice = MockBeatrice::new(ir, or, ic, oc)?;
+            beatrice.load_model(&dir)?;
+            beatrice.set_pitch_shift(-12.0)?;
+
+            Ok(Box::new(beatrice))
+        })
+        .unwrap();
+
+        let reader = WavReader::open(&output_path).unwrap();
+        assert_eq!(reader.spec().sample_rate, 48000);
+        assert_eq!(reader.spec().channels, 1);
+        assert_eq!(reader.duration(), 48000);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_latency_alignment() {
+        let mut beatrice = MockBeatrice::new(48000.0, 44100.0, 1, 1).unwrap();
+        beatrice.load_model("mock").unwrap();
+
+        let mut input = vec![0.0; 48000];
+        input[24000] = 1.0;
+
+        let output = convert_samples(&mut beatrice, &input, 48000, 1, 44100, 1).unwrap();
+        assert_eq!(output.len(), 44100);
+
+        let peak = output
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+            .unwrap()
+            .0;
+        assert!(peak.abs_diff(22050) <= 3);
+    }
+
+    #[test]
+    fn test_calibrate_wav() {
+        let dir = std::env::temp_dir().join("beatrice_lib_test_calibrate_wav");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let input_path = dir.join("input.wav");
+
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 16000,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+
+        // 220Hz は MIDI ノート番号 57
+        let mut writer = WavWriter::create(&input_path, spec).unwrap();
+        for i in 0..16000 {
+            let t = i as f32 / 16000.0;
+            writer
+                .write_sample((t * 220.0 * std::f32::consts::TAU).sin() * 0.5)
+                .unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let calibration = calibrate_wav(&input_path, |ir, or, ic, oc| {
+            let mut beatrice = MockBeatrice::new(ir, or, ic, oc)?;
+            beatrice.load_model(&dir)?;
+
+            Ok(Box::new(beatrice))
+        })
+        .unwrap();
+
+        assert!((calibration.average_source_pitch - 57.0).abs() <= 0.125);
+        assert!(0 < calibration.voiced_hops);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,220 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::BeatriceError, speaker_mix::SpeakerWeight};
+
+/// ピッチ補正の方法
+///
+/// 補正の強さは `pitch_correction` (0.0 で無効、1.0 で完全に半音に合わせる) で決まる
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum PitchCorrectionMode {
+    /// 補正しない
+    #[default]
+    Off,
+    /// 半音と半音の中間から遠ざけるように補正する (旧 `pitch_correction_type` = 0)
+    Push,
+    /// 最も近い半音に引き寄せるように補正する (旧 `pitch_correction_type` = 1)
+    Pull,
+    /// 指定したキーとスケールの音のうち、最も近いものに引き寄せるように補正する
+    Scale(ScaleCorrection),
+}
+
+impl PitchCorrectionMode {
+    #[cfg_attr(not(any(feature = "native", feature = "mock")), allow(dead_code))]
+    pub(crate) fn validate(&self, capabilities: &Capabilities) -> Result<(), BeatriceError> {
+        let PitchCorrectionMode::Scale(scale) = self else {
+            return Ok(());
+        };
+
+        if 12 <= scale.key {
+            return Err(BeatriceError::ParameterOutOfRange("key", scale.key as f64));
+        }
+        if !scale.scale.notes().contains(&true) {
+            return Err(BeatriceError::EmptyScale);
+        }
+        capabilities
+            .reference_frequency
+            .validate("reference_frequency", scale.reference_frequency)?;
+
+        Ok(())
+    }
+}
+
+/// スケール補正の設定
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScaleCorrection {
+    /// 主音 (0 = C, 1 = C#, ..., 11 = B)
+    pub key: u32,
+    pub scale: Scale,
+    /// A4 の周波数 (Hz)
+    pub reference_frequency: f64,
+}
+
+impl Default for ScaleCorrection {
+    fn default() -> Self {
+        Self {
+            key: 0,
+            scale: Scale::Major,
+            reference_frequency: 440.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scale {
+    Major,
+    /// 自然的短音階
+    Minor,
+    MajorPentatonic,
+    MinorPentatonic,
+    /// 主音から数えた半音 12 個それぞれを使うかどうか
+    Custom([bool; 12]),
+}
+
+impl Scale {
+    /// 主音から数えた半音 12 個それぞれがスケールに含まれるかどうか
+    pub fn notes(&self) -> [bool; 12] {
+        let degrees: &[usize] = match self {
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::Custom(notes) => return *notes,
+        };
+
+        let mut notes = [false; 12];
+        for &degree in degrees {
+            notes[degree] = true;
+        }
+        notes
+    }
+}
+
+/// 現在の声の設定
+///
+/// RC.0 より前のモデルでは `min_source_pitch`, `max_source_pitch`, `vq_num_neighbors` は `None`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VoiceParameters {
+    pub target_speaker: u32,
+    /// 混ぜている話者と重み (重い順、合計 1)。1 人だけなら `target_speaker` の重み 1
+    pub speaker_mix: Vec<SpeakerWeight>,
+    pub pitch_shift: f64,
+    /// 有効なら `pitch_shift` はターゲットの声の平均ピッチと `average_source_pitch` の差から決まる
+    pub auto_pitch_shift: bool,
+    pub formant_shift: f64,
+    /// 入力の声の平均の音高 (MIDI ノート番号)。抑揚の中心と自動のピッチシフトに使う
+    pub average_source_pitch: f64,
+    pub intonation_intensity: f64,
+    pub pitch_correction: f64,
+    pub pitch_correction_mode: PitchCorrectionMode,
+    pub pitch_glide_time: f64,
+    pub pitch_median_window: u32,
+    pub pitch_hysteresis: f64,
+    pub min_source_pitch: Option<f64>,
+    pub max_source_pitch: Option<f64>,
+    pub vq_num_neighbors: Option<i32>,
+}
+
+/// 設定値の範囲、刻み幅、初期値
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ParameterRange {
+    pub min: f64,
+    pub max: f64,
+    pub step: f64,
+    pub default: f64,
+}
+
+impl ParameterRange {
+    pub const fn new(min: f64, max: f64, step: f64, default: f64) -> Self {
+        Self {
+            min,
+            max,
+            step,
+            default,
+        }
+    }
+
+    /// 範囲内ならそのまま返し、範囲外 (NaN を含む) なら `BeatriceError::ParameterOutOfRange` を返す
+    pub fn validate(&self, name: &'static str, value: f64) -> Result<f64, BeatriceError> {
+        if !(self.min..=self.max).contains(&value) {
+            return Err(BeatriceError::ParameterOutOfRange(name, value));
+        }
+
+        Ok(value)
+    }
+}
+
+/// ソースとターゲットの平均ピッチ (MIDI ノート番号) の差を半音単位に丸めて、ピッチシフトの範囲に収める
+#[cfg_attr(not(any(feature = "native", feature = "mock")), allow(dead_code))]
+pub(crate) fn auto_pitch_shift(
+    average_source_pitch: f64,
+    average_target_pitch: f64,
+    range: ParameterRange,
+) -> f64 {
+    (average_target_pitch - average_source_pitch)
+        .round()
+        .clamp(range.min, range.max)
+}
+
+/// `ParameterRange::validate` と同じだが、対応していない (`None`) 設定なら `BeatriceError::UnsupportedParameter` を返す
+#[cfg_attr(not(any(feature = "native", feature = "mock")), allow(dead_code))]
+pub(crate) fn validate_supported(
+    range: Option<ParameterRange>,
+    name: &'static str,
+    value: f64,
+) -> Result<f64, BeatriceError> {
+    match range {
+        Some(range) => range.validate(name, value),
+        None => Err(BeatriceError::UnsupportedParameter(name)),
+    }
+}
+
+/// モデルが対応している設定とその範囲
+///
+/// 対応していない設定は `None` で、その setter は `BeatriceError::UnsupportedParameter` を返す
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub pitch_shift: ParameterRange,
+    /// 0.5 刻みの 9 つの埋め込みの間は線形補間する
+    pub formant_shift: ParameterRange,
+    pub average_source_pitch: ParameterRange,
+    pub intonation_intensity: ParameterRange,
+    pub pitch_correction: ParameterRange,
+    /// `PitchCorrectionMode::Scale` の A4 の周波数 (Hz)
+    pub reference_frequency: ParameterRange,
+    /// ピッチが目標に近づくまでの時定数 (ms)
+    pub pitch_glide_time: ParameterRange,
+    /// ピッチのメディアンフィルタの幅 (hop 数)
+    pub pitch_median_window: ParameterRange,
+    /// ピッチを動かさない変化の幅 (半音)
+    pub pitch_hysteresis: ParameterRange,
+    pub min_source_pitch: Option<ParameterRange>,
+    pub max_source_pitch: Option<ParameterRange>,
+    pub vq_num_neighbors: Option<ParameterRange>,
+}
+
+impl Capabilities {
+    /// 2.0.0-alpha, 2.0.0-beta.1
+    pub const BETA: Self = Self {
+        pitch_shift: ParameterRange::new(-24.0, 24.0, 0.125, 0.0),
+        formant_shift: ParameterRange::new(-2.0, 2.0, 0.01, 0.0),
+        average_source_pitch: ParameterRange::new(33.125, 88.875, 0.125, 60.0),
+        intonation_intensity: ParameterRange::new(-1.0, 3.0, 0.1, 1.0),
+        pitch_correction: ParameterRange::new(0.0, 1.0, 0.01, 0.0),
+        reference_frequency: ParameterRange::new(400.0, 480.0, 0.1, 440.0),
+        pitch_glide_time: ParameterRange::new(0.0, 500.0, 1.0, 0.0),
+        pitch_median_window: ParameterRange::new(1.0, 9.0, 1.0, 1.0),
+        pitch_hysteresis: ParameterRange::new(0.0, 1.0, 0.01, 0.0),
+        min_source_pitch: None,
+        max_source_pitch: None,
+        vq_num_neighbors: None,
+    };
+
+    /// 2.0.0-rc.0
+    pub const RC0: Self = Self {
+        average_source_pitch: ParameterRange::new(33.125, 88.875, 0.125, 52.0),
+        min_source_pitch: Some(ParameterRange::new(33.125, 88.875, 0.125, 33.125)),
+        max_source_pitch: Some(ParameterRange::new(33.125, 88.875, 0.125, 80.875)),
+        vq_num_neighbors: Some(ParameterRange::new(0.0, 8.0, 1.0, 0.0)),
+        ..Self::BETA
+    };
+}
@@ -0,0 +1,461 @@
+use crate::{
+    constants::{BEATRICE_IN_HOP_LENGTH, BEATRICE_IN_SAMPLE_RATE, BEATRICE_PITCH_BINS_PER_OCTAVE},
+    parameters::{PitchCorrectionMode, ScaleCorrection},
+};
+
+const PITCH_BINS_PER_SEMITONE: f64 = BEATRICE_PITCH_BINS_PER_OCTAVE as f64 / 12.0;
+
+const HOP_MS: f64 = BEATRICE_IN_HOP_LENGTH as f64 * 1000.0 / BEATRICE_IN_SAMPLE_RATE as f64;
+
+pub(crate) const MAX_MEDIAN_WINDOW: usize = 9;
+
+// quantized pitch の 0 番目のビンに当たる MIDI ノート番号 (A4 = 440Hz のとき A1)
+const PITCH_BIN_ORIGIN_NOTE: f64 = 33.0;
+
+/// 推論ごとに quantized pitch に掛ける設定
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PitchParameters {
+    /// 抑揚の中心にする音高 (MIDI ノート番号)
+    pub average_source_pitch: f64,
+    pub intonation_intensity: f64,
+    pub pitch_shift: f64,
+    pub pitch_correction: f64,
+    pub pitch_correction_mode: PitchCorrectionMode,
+    /// 目標の音高に近づくまでの時定数 (ms)。0.0 なら即座に変わる
+    pub pitch_glide_time: f64,
+    /// 推定した音高に掛けるメディアンフィルタの幅 (hop 数)。1 なら掛けない
+    pub pitch_median_window: u32,
+    /// 目標の音高をこの幅 (半音) より小さい変化では動かさない
+    pub pitch_hysteresis: f64,
+}
+
+/// ピッチの平滑化のために hop をまたいで持ち越す状態
+#[derive(Debug, Default)]
+pub(crate) struct PitchState {
+    // 推定した quantized pitch のリングバッファ
+    history: [f64; MAX_MEDIAN_WINDOW],
+    history_len: usize,
+    history_pos: usize,
+
+    // ヒステリシスで保持している目標の音高
+    held: Option<f64>,
+    // グライド中の今の音高
+    current: Option<f64>,
+}
+
+impl PitchState {
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// 推定値を履歴に入れ、直近 `window` 個のメディアンを返す
+    fn median(&mut self, quantized_pitch: f64, window: usize) -> f64 {
+        self.history[self.history_pos] = quantized_pitch;
+        self.history_pos = (self.history_pos + 1) % MAX_MEDIAN_WINDOW;
+        self.history_len = (self.history_len + 1).min(MAX_MEDIAN_WINDOW);
+
+        let n = window.clamp(1, self.history_len);
+        let mut recent = [0.0; MAX_MEDIAN_WINDOW];
+        for (i, v) in recent[..n].iter_mut().enumerate() {
+            *v = self.history[(self.history_pos + MAX_MEDIAN_WINDOW - 1 - i) % MAX_MEDIAN_WINDOW];
+        }
+
+        let recent = &mut recent[..n];
+        recent.sort_by(f64::total_cmp);
+
+        if n % 2 == 1 {
+            recent[n / 2]
+        } else {
+            (recent[n / 2 - 1] + recent[n / 2]) / 2.0
+        }
+    }
+
+    /// ヒステリシスとグライドを掛ける
+    fn smooth(&mut self, target: f64, parameters: &PitchParameters) -> f64 {
+        let hysteresis = parameters.pitch_hysteresis * PITCH_BINS_PER_SEMITONE;
+        let held = match self.held {
+            Some(held) if (target - held).abs() < hysteresis => held,
+            _ => target,
+        };
+        self.held = Some(held);
+
+        let current = match self.current {
+            Some(current) if 0.0 < parameters.pitch_glide_time => {
+                let alpha = 1.0 - (-HOP_MS / parameters.pitch_glide_time).exp();
+                current + (held - current) * alpha
+            }
+            _ => held,
+        };
+        self.current = Some(current);
+
+        current
+    }
+}
+
+/// 推定した quantized pitch にピッチシフト、抑揚、ピッチ補正、平滑化を掛け、`1..pitch_bins` に収める
+pub(crate) fn process_pitch(
+    quantized_pitch: i32,
+    pitch_bins: u32,
+    parameters: &PitchParameters,
+    state: &mut PitchState,
+) -> i32 {
+    // MedianFilter
+    let quantized_pitch = state.median(
+        quantized_pitch as f64,
+        parameters.pitch_median_window as usize,
+    );
+
+    // PitchShift, IntonationIntensity
+    let average_source_pitch = note_to_quantized_pitch(parameters.average_source_pitch);
+    let mut tmp_quantized_pitch = average_source_pitch
+        + (quantized_pitch - average_source_pitch) * parameters.intonation_intensity
+        + PITCH_BINS_PER_SEMITONE * parameters.pitch_shift;
+
+    // PitchCorrection
+    if parameters.pitch_correction != 0.0 {
+        tmp_quantized_pitch = match parameters.pitch_correction_mode {
+            PitchCorrectionMode::Off => tmp_quantized_pitch,
+            PitchCorrectionMode::Push => {
+                correct_push(tmp_quantized_pitch, parameters.pitch_correction)
+            }
+            PitchCorrectionMode::Pull => {
+                correct_pull(tmp_quantized_pitch, parameters.pitch_correction)
+            }
+            PitchCorrectionMode::Scale(scale) => {
+                correct_to_scale(tmp_quantized_pitch, &scale, parameters.pitch_correction)
+            }
+        };
+    }
+
+    // Hysteresis, Glide
+    let tmp_quantized_pitch = state.smooth(tmp_quantized_pitch, parameters);
+
+    let rounded = tmp_quantized_pitch.round() as i32;
+    rounded.clamp(1, pitch_bins as i32 - 1)
+}
+
+/// MIDI ノート番号 (A4 = 440Hz) を quantized pitch に変換する
+pub(crate) fn note_to_quantized_pitch(note: f64) -> f64 {
+    (note - PITCH_BIN_ORIGIN_NOTE) * PITCH_BINS_PER_SEMITONE
+}
+
+/// quantized pitch を MIDI ノート番号 (A4 = 440Hz) に変換する
+pub(crate) fn quantized_pitch_to_note(quantized_pitch: f64) -> f64 {
+    quantized_pitch / PITCH_BINS_PER_SEMITONE + PITCH_BIN_ORIGIN_NOTE
+}
+
+/// 半音と半音の中間から遠ざけるように補正する
+fn correct_push(quantized_pitch: f64, strength: f64) -> f64 {
+    let nearest_pitch =
+        ((quantized_pitch / PITCH_BINS_PER_SEMITONE).floor() + 0.5) * PITCH_BINS_PER_SEMITONE;
+
+    let normalized_delta = (quantized_pitch - nearest_pitch) * (2.0 / PITCH_BINS_PER_SEMITONE);
+
+    if normalized_delta.abs() < 1e-4 {
+        nearest_pitch
+    } else {
+        nearest_pitch
+            + normalized_delta
+                * (normalized_delta.abs().powf(-strength))
+                * (PITCH_BINS_PER_SEMITONE / 2.0)
+    }
+}
+
+/// 最も近い半音に引き寄せるように補正する
+fn correct_pull(quantized_pitch: f64, strength: f64) -> f64 {
+    let nearest_pitch =
+        (quantized_pitch / PITCH_BINS_PER_SEMITONE).round() * PITCH_BINS_PER_SEMITONE;
+
+    let normalized_delta = (quantized_pitch - nearest_pitch) * (2.0 / PITCH_BINS_PER_SEMITONE);
+
+    if strength > (1.0 - 1e-4) {
+        nearest_pitch
+    } else if normalized_delta >= 0.0 {
+        nearest_pitch
+            + normalized_delta.powf(1.0 / (1.0 - strength)) * (PITCH_BINS_PER_SEMITONE / 2.0)
+    } else {
+        nearest_pitch
+            - (-normalized_delta).powf(1.0 / (1.0 - strength)) * (PITCH_BINS_PER_SEMITONE / 2.0)
+    }
+}
+
+/// quantized pitch をキーとスケールに合わせて補正する
+///
+/// 前後のスケールの音の間を `PitchCorrectionMode::Pull` と同じ曲線で近い方に引き寄せる。
+/// `strength` が 1.0 ならスケールの音にそのまま合わせる
+fn correct_to_scale(quantized_pitch: f64, scale: &ScaleCorrection, strength: f64) -> f64 {
+    let notes = scale.scale.notes();
+    if !notes.contains(&true) {
+        return quantized_pitch;
+    }
+
+    // 主音を 0 とした半音単位の音高
+    let offset = PITCH_BIN_ORIGIN_NOTE
+        - scale.key as f64
+        - 12.0 * (scale.reference_frequency / 440.0).log2();
+    let note = quantized_pitch / PITCH_BINS_PER_SEMITONE + offset;
+
+    let in_scale = |n: f64| notes[(n as i64).rem_euclid(12) as usize];
+
+    let mut lower = note.floor();
+    while !in_scale(lower) {
+        lower -= 1.0;
+    }
+    let mut upper = note.ceil();
+    while !in_scale(upper) {
+        upper += 1.0;
+    }
+
+    if lower == upper {
+        return quantized_pitch;
+    }
+
+    let half = (upper - lower) / 2.0;
+    let nearest = if note - lower < upper - note {
+        lower
+    } else {
+        upper
+    };
+
+    let corrected = if strength > (1.0 - 1e-4) {
+        nearest
+    } else {
+        let normalized_delta = (note - nearest) / half;
+        nearest
+            + normalized_delta.signum() * normalized_delta.abs().powf(1.0 / (1.0 - strength)) * half
+    };
+
+    (corrected - offset) * PITCH_BINS_PER_SEMITONE
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        PitchCorrectionMode, Scale, ScaleCorrection,
+        pitch::{
+            PitchParameters, PitchState, correct_pull, correct_push, correct_to_scale,
+            note_to_quantized_pitch, process_pitch, quantized_pitch_to_note,
+        },
+    };
+
+    const PARAMETERS: PitchParameters = PitchParameters {
+        // quantized pitch の 52 ビン目
+        average_source_pitch: 39.5,
+        intonation_intensity: 1.0,
+        pitch_shift: 0.0,
+        pitch_correction: 0.0,
+        pitch_correction_mode: PitchCorrectionMode::Off,
+        pitch_glide_time: 0.0,
+        pitch_median_window: 1,
+        pitch_hysteresis: 0.0,
+    };
+
+    fn process(quantized_pitch: i32, pitch_bins: u32, parameters: &PitchParameters) -> i32 {
+        process_pitch(
+            quantized_pitch,
+            pitch_bins,
+            parameters,
+            &mut PitchState::default(),
+        )
+    }
+
+    // MIDI ノート番号から quantized pitch へ (A4 = 440Hz)
+    fn bins(note: f64) -> f64 {
+        (note - 33.0) * 8.0
+    }
+
+    #[test]
+    fn test_note_conversion() {
+        assert_eq!(note_to_quantized_pitch(33.0), 0.0);
+        assert_eq!(note_to_quantized_pitch(52.0), 152.0);
+        assert_eq!(quantized_pitch_to_note(152.0), 52.0);
+        assert_eq!(quantized_pitch_to_note(note_to_quantized_pitch(69.5)), 69.5);
+    }
+
+    #[test]
+    fn test_shift_and_intonation() {
+        assert_eq!(process(100, 448, &PARAMETERS), 100);
+
+        let shifted = PitchParameters {
+            pitch_shift: 12.0,
+            ..PARAMETERS
+        };
+        assert_eq!(process(100, 448, &shifted), 196);
+
+        // 平均からの差が倍になる
+        let intonation = PitchParameters {
+            intonation_intensity: 2.0,
+            ..PARAMETERS
+        };
+        assert_eq!(process(62, 448, &intonation), 72);
+        assert_eq!(process(42, 448, &intonation), 32);
+    }
+
+    #[test]
+    fn test_average_source_pitch_is_note() {
+        // average_source_pitch は MIDI ノート番号なので、52 (E3) は 152 ビン目が中心になる
+        let intonation = PitchParameters {
+            average_source_pitch: 52.0,
+            intonation_intensity: 2.0,
+            ..PARAMETERS
+        };
+        assert_eq!(process(162, 448, &intonation), 172);
+        assert_eq!(process(142, 448, &intonation), 132);
+    }
+
+    #[test]
+    fn test_clamp_to_bins() {
+        let shifted = PitchParameters {
+            pitch_shift: 24.0,
+            ..PARAMETERS
+        };
+        assert_eq!(process(380, 384, &shifted), 383);
+        assert_eq!(process(380, 448, &shifted), 447);
+
+        let shifted = PitchParameters {
+            pitch_shift: -24.0,
+            ..PARAMETERS
+        };
+        assert_eq!(process(10, 384, &shifted), 1);
+    }
+
+    #[test]
+    fn test_push_and_pull() {
+        for i in 0..80 {
+            let pitch = 100.0 + i as f64 * 0.1;
+            let semitone = (pitch / 8.0).round() * 8.0;
+
+            for strength in [0.0, 0.3, 0.7, 1.0] {
+                let pushed = correct_push(pitch, strength);
+                assert!((pushed - semitone).abs() <= (pitch - semitone).abs() + 1e-4);
+
+                let pulled = correct_pull(pitch, strength);
+                assert!((pulled - semitone).abs() <= (pitch - semitone).abs() + 1e-4);
+            }
+
+            assert!((correct_pull(pitch, 1.0) - semitone).abs() < 1e-9);
+        }
+
+        // 強さが 0 なら補正自体をしない
+        let off = PitchParameters {
+            pitch_correction_mode: PitchCorrectionMode::Pull,
+            ..PARAMETERS
+        };
+        assert_eq!(process(101, 448, &off), 101);
+
+        let pull = PitchParameters {
+            pitch_correction: 1.0,
+            ..off
+        };
+        assert_eq!(process(101, 448, &pull), 104);
+    }
+
+    #[test]
+    fn test_median() {
+        let parameters = PitchParameters {
+            pitch_median_window: 3,
+            ..PARAMETERS
+        };
+
+        // 1 hop だけ飛んだ値は無視される
+        let mut state = PitchState::default();
+        let output =
+            [100, 100, 196, 100, 100].map(|p| process_pitch(p, 448, &parameters, &mut state));
+        assert_eq!(output, [100, 100, 100, 100, 100]);
+
+        // 2 hop 続けば追従する
+        let output = [196, 196, 196].map(|p| process_pitch(p, 448, &parameters, &mut state));
+        assert_eq!(output, [100, 196, 196]);
+    }
+
+    #[test]
+    fn test_hysteresis_and_glide() {
+        let hysteresis = PitchParameters {
+            pitch_hysteresis: 0.5,
+            ..PARAMETERS
+        };
+
+        let mut state = PitchState::default();
+        let output =
+            [100, 102, 98, 104, 103].map(|p| process_pitch(p, 448, &hysteresis, &mut state));
+        assert_eq!(output, [100, 100, 100, 104, 104]);
+
+        let glide = PitchParameters {
+            pitch_glide_time: 50.0,
+            ..PARAMETERS
+        };
+
+        let mut state = PitchState::default();
+        process_pitch(100, 448, &glide, &mut state);
+
+        let mut previous = 100;
+        for _ in 0..10 {
+            let output = process_pitch(196, 448, &glide, &mut state);
+            assert!(previous <= output && output < 196);
+            previous = output;
+        }
+        for _ in 0..100 {
+            previous = process_pitch(196, 448, &glide, &mut state);
+        }
+        assert_eq!(previous, 196);
+
+        state.clear();
+        assert_eq!(process_pitch(100, 448, &glide, &mut state), 100);
+    }
+
+    #[test]
+    fn test_snap_to_scale() {
+        let c_major = ScaleCorrection::default();
+
+        // C#4 + 0.1 は C4 と D4 の間で D4 寄り
+        let corrected = correct_to_scale(bins(61.1), &c_major, 1.0);
+        assert!((corrected - bins(62.0)).abs() < 1e-9);
+
+        // E4 と F4 は隣り合っているのでそのまま近い方
+        let corrected = correct_to_scale(bins(64.4), &c_major, 1.0);
+        assert!((corrected - bins(64.0)).abs() < 1e-9);
+
+        // 強さ 0 なら変えない
+        let corrected = correct_to_scale(bins(61.1), &c_major, 0.0);
+        assert!((corrected - bins(61.1)).abs() < 1e-9);
+
+        // 強さ 0.5 なら元の音とスケールの音の間
+        let corrected = correct_to_scale(bins(61.6), &c_major, 0.5);
+        assert!(bins(61.6) < corrected && corrected < bins(62.0));
+    }
+
+    #[test]
+    fn test_key_and_custom_scale() {
+        // A マイナーペンタトニック (A, C, D, E, G)
+        let a_minor_pentatonic = ScaleCorrection {
+            key: 9,
+            scale: Scale::MinorPentatonic,
+            ..Default::default()
+        };
+        let corrected = correct_to_scale(bins(70.4), &a_minor_pentatonic, 1.0);
+        assert!((corrected - bins(69.0)).abs() < 1e-9);
+
+        // 主音だけ
+        let mut notes = [false; 12];
+        notes[0] = true;
+        let only_root = ScaleCorrection {
+            key: 2,
+            scale: Scale::Custom(notes),
+            ..Default::default()
+        };
+        let corrected = correct_to_scale(bins(55.0), &only_root, 1.0);
+        assert!((corrected - bins(50.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_reference_frequency() {
+        // A4 = 466.16Hz (半音上) なら、440Hz 基準の A#4 がスケール上の A4 になる
+        let a_major = ScaleCorrection {
+            key: 9,
+            reference_frequency: 440.0 * 2.0_f64.powf(1.0 / 12.0),
+            ..Default::default()
+        };
+        let corrected = correct_to_scale(bins(70.2), &a_major, 1.0);
+        assert!((corrected - bins(70.0)).abs() < 1e-9);
+    }
+}
@@ -1,6 +1,45 @@
 use rubato::{
     Resampler, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction,
 };
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    beatrice::Latency,
+    constants::{
+        BEATRICE_IN_HOP_LENGTH, BEATRICE_IN_SAMPLE_RATE, BEATRICE_OUT_HOP_LENGTH,
+        BEATRICE_OUT_SAMPLE_RATE,
+    },
+    errors::BeatriceError,
+};
+
+const MIN_SAMPLE_RATE: f64 = 8000.0;
+const MAX_SAMPLE_RATE: f64 = 384000.0;
+
+/// 入力を Beatrice に渡すモノラルにする方法
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Downmix {
+    /// 全チャンネルの平均
+    #[default]
+    Average,
+    /// 指定したチャンネル (0 始まり) だけを使う
+    Channel(u32),
+}
+
+/// Beatrice の出力 (モノラル) を出力チャンネルに配る方法
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Upmix {
+    /// 全チャンネルに同じ音を出す
+    #[default]
+    DuplicateToAll,
+    /// 最初の 2 チャンネルにだけ出し、残りは無音にする
+    DuplicateToFirstTwo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct ChannelMix {
+    pub downmix: Downmix,
+    pub upmix: Upmix,
+}
 
 pub struct BeatriceResampler {
     in_resampler: SincFixedIn<f32>,
@@ -10,6 +49,18 @@ pub struct BeatriceResampler {
     out_sample_rate: f64,
     in_channel: u32,
     out_channel: u32,
+    channel_mix: ChannelMix,
+
+    // in_resampler に渡す前のモノラル音声
+    in_fifo: Vec<f32>,
+    // 16kHz に変換したあと、1 hop に満たずに残っている音声
+    hop_fifo: Vec<f32>,
+    // out_resampler に渡す前の 24kHz の音声
+    out_fifo: Vec<f32>,
+    // モデル自体の遅延 (24kHz のサンプル数)
+    model_delay: usize,
+    // モデルの出力の先頭で、まだ捨てていない遅延の分
+    model_delay_remaining: usize,
 }
 
 impl BeatriceResampler {
@@ -18,9 +69,11 @@ impl BeatriceResampler {
         out_sample_rate: f64,
         in_channel: u32,
         out_channel: u32,
-    ) -> Self {
+    ) -> Result<Self, BeatriceError> {
+        Self::validate(in_sample_rate, out_sample_rate, in_channel, out_channel)?;
+
         let in_resampler = SincFixedIn::<f32>::new(
-            16000.0 / in_sample_rate,
+            BEATRICE_IN_SAMPLE_RATE as f64 / in_sample_rate,
             2.0,
             SincInterpolationParameters {
                 sinc_len: 128,
@@ -31,11 +84,10 @@ impl BeatriceResampler {
             },
             (in_sample_rate / 100.0).round() as usize,
             1,
-        )
-        .unwrap();
+        )?;
 
         let out_resampler = SincFixedIn::<f32>::new(
-            out_sample_rate / 24000.0,
+            out_sample_rate / BEATRICE_OUT_SAMPLE_RATE as f64,
             2.0,
             SincInterpolationParameters {
                 sinc_len: 128,
@@ -44,62 +96,282 @@ impl BeatriceResampler {
                 oversampling_factor: 64,
                 window: WindowFunction::BlackmanHarris2,
             },
-            240,
+            BEATRICE_OUT_HOP_LENGTH as usize,
             1,
-        )
-        .unwrap();
+        )?;
 
-        Self {
+        Ok(Self {
             in_resampler,
             out_resampler,
             in_sample_rate,
             out_sample_rate,
             in_channel,
             out_channel,
-        }
+            channel_mix: ChannelMix::default(),
+            in_fifo: vec![],
+            hop_fifo: vec![],
+            out_fifo: vec![],
+            model_delay: 0,
+            model_delay_remaining: 0,
+        })
     }
 
-    pub fn convert_to_beatrice_input(&mut self, input: &[f32]) -> Vec<f32> {
-        let mut mono = vec![0.0; (self.in_sample_rate / 100.0).round() as usize];
-        match self.in_channel {
-            1 => {
-                for (i, d) in input.iter().enumerate() {
-                    mono[i] = *d;
-                }
+    /// サンプリングレートとチャンネル数が扱えるものか調べる
+    pub fn validate(
+        in_sample_rate: f64,
+        out_sample_rate: f64,
+        in_channel: u32,
+        out_channel: u32,
+    ) -> Result<(), BeatriceError> {
+        for sample_rate in [in_sample_rate, out_sample_rate] {
+            if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&sample_rate) {
+                return Err(BeatriceError::InvalidSampleRate(sample_rate));
             }
-            2 => {
-                for (i, chunk) in input.chunks_exact(2).enumerate() {
-                    mono[i] = (chunk[0] + chunk[1]) / 2.0;
-                }
+        }
+
+        for channel in [in_channel, out_channel] {
+            if channel == 0 {
+                return Err(BeatriceError::UnsupportedChannelLayout(channel));
             }
-            _ => panic!("in_channel must be 1 or 2"),
-        };
+        }
 
-        self.in_resampler.process(&[mono], None).unwrap().remove(0)
+        Ok(())
     }
 
-    pub fn convert_from_beatrice_output(&mut self, processed: &[f32]) -> Vec<f32> {
-        let out = match self.out_resampler.process(&[processed], None) {
-            Ok(v) => v,
-            Err(e) => {
-                dbg!(e);
-                return Vec::new();
-            }
+    pub fn set_channel_mix(&mut self, channel_mix: ChannelMix) -> Result<(), BeatriceError> {
+        if let Downmix::Channel(channel) = channel_mix.downmix
+            && self.in_channel <= channel
+        {
+            return Err(BeatriceError::InvalidChannelIndex(channel));
+        }
+
+        self.channel_mix = channel_mix;
+        Ok(())
+    }
+
+    /// モデル自体の遅延 (24kHz のサンプル数) を設定する
+    ///
+    /// リサンプラーの遅延と同じく、出力の先頭の遅延分を捨てて先読みとして扱う
+    pub fn set_model_delay(&mut self, model_delay: usize) {
+        self.model_delay = model_delay;
+        self.model_delay_remaining = model_delay;
+    }
+
+    /// バッファとリサンプラーの内部状態を捨てる
+    pub fn reset(&mut self) {
+        self.in_resampler.reset();
+        self.out_resampler.reset();
+
+        self.in_fifo.clear();
+        self.hop_fifo.clear();
+        self.out_fifo.clear();
+        self.model_delay_remaining = self.model_delay;
+    }
+
+    /// 入力してから出力されるまでの遅延
+    ///
+    /// 1 hop 分の入力を溜める時間と、2 つのリサンプラーとモデルの先読みの分
+    pub fn latency(&self) -> Latency {
+        let seconds = BEATRICE_IN_HOP_LENGTH as f64 / BEATRICE_IN_SAMPLE_RATE as f64
+            + self.in_resampler.output_delay() as f64 / BEATRICE_IN_SAMPLE_RATE as f64
+            + self.model_delay as f64 / BEATRICE_OUT_SAMPLE_RATE as f64
+            + self.out_resampler.output_delay() as f64 / self.out_sample_rate;
+
+        Latency {
+            input_frames: (seconds * self.in_sample_rate).round() as usize,
+            output_frames: (seconds * self.out_sample_rate).round() as usize,
+        }
+    }
+
+    /// バッファに残っている音声を出し切るために流す無音 (インターリーブ済み)
+    ///
+    /// リサンプラーとモデルの遅延に加えて、入力のチャンク・16kHz の hop・24kHz の hop の端数の分 (それぞれ 10ms) を足す
+    pub fn flush_input(&self) -> Vec<f32> {
+        let chunk_frames = (self.in_sample_rate / 100.0).round() as usize;
+        let frames = self.latency().input_frames + chunk_frames * 3;
+
+        vec![0.0; frames * self.in_channel as usize]
+    }
+
+    /// 任意の長さのインターリーブされた音声を受け取り、16kHz のモノラルに変換する
+    ///
+    /// 返すのは揃った hop (`BEATRICE_IN_HOP_LENGTH` サンプル) の分だけで、残りは次の呼び出しに持ち越す
+    pub fn convert_to_beatrice_input(&mut self, input: &[f32]) -> Result<Vec<f32>, BeatriceError> {
+        let in_channel = self.in_channel as usize;
+
+        for frame in input.chunks_exact(in_channel) {
+            self.in_fifo.push(match self.channel_mix.downmix {
+                Downmix::Average => frame.iter().sum::<f32>() / in_channel as f32,
+                Downmix::Channel(channel) => frame[channel as usize],
+            });
+        }
+
+        while self.in_resampler.input_frames_next() <= self.in_fifo.len() {
+            let frames = self.in_resampler.input_frames_next();
+            let resampled = self
+                .in_resampler
+                .process(&[&self.in_fifo[..frames]], None)?;
+
+            self.in_fifo.drain(..frames);
+            self.hop_fifo.extend_from_slice(&resampled[0]);
+        }
+
+        let hop_len = BEATRICE_IN_HOP_LENGTH as usize;
+        let complete = self.hop_fifo.len() / hop_len * hop_len;
+
+        Ok(self.hop_fifo.drain(..complete).collect())
+    }
+
+    /// Beatrice の出力 (24kHz のモノラル) を受け取り、出力のサンプリングレートとチャンネル数に変換する
+    ///
+    /// `BEATRICE_OUT_HOP_LENGTH` サンプルに満たない分は次の呼び出しに持ち越す
+    pub fn convert_from_beatrice_output(
+        &mut self,
+        processed: &[f32],
+    ) -> Result<Vec<f32>, BeatriceError> {
+        let skip = self.model_delay_remaining.min(processed.len());
+        self.model_delay_remaining -= skip;
+        self.out_fifo.extend_from_slice(&processed[skip..]);
+
+        let out_channel = self.out_channel as usize;
+        let n_duplicate = match self.channel_mix.upmix {
+            Upmix::DuplicateToAll => out_channel,
+            Upmix::DuplicateToFirstTwo => out_channel.min(2),
         };
 
-        let mono = &out[0];
-
-        match self.out_channel {
-            1 => mono.clone(),
-            2 => {
-                let mut stereo = Vec::with_capacity(mono.len() * 2);
-                for &s in mono {
-                    stereo.push(s);
-                    stereo.push(s);
-                }
-                stereo
+        let mut output = vec![];
+        while self.out_resampler.input_frames_next() <= self.out_fifo.len() {
+            let frames = self.out_resampler.input_frames_next();
+            let resampled = self
+                .out_resampler
+                .process(&[&self.out_fifo[..frames]], None)?;
+
+            self.out_fifo.drain(..frames);
+
+            let start = output.len();
+            output.resize(start + resampled[0].len() * out_channel, 0.0);
+            for (frame, &s) in output[start..]
+                .chunks_exact_mut(out_channel)
+                .zip(&resampled[0])
+            {
+                frame[..n_duplicate].fill(s);
             }
-            _ => panic!("out_channel must be 1 or 2"),
         }
+
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BeatriceError, BeatriceResampler, ChannelMix, Downmix, Upmix};
+
+    #[test]
+    fn test_validate() {
+        assert!(BeatriceResampler::new(48000.0, 48000.0, 6, 8).is_ok());
+
+        assert!(matches!(
+            BeatriceResampler::new(0.0, 48000.0, 1, 1),
+            Err(BeatriceError::InvalidSampleRate(_))
+        ));
+        assert!(matches!(
+            BeatriceResampler::new(48000.0, f64::NAN, 1, 1),
+            Err(BeatriceError::InvalidSampleRate(_))
+        ));
+        assert!(matches!(
+            BeatriceResampler::new(48000.0, 48000.0, 0, 1),
+            Err(BeatriceError::UnsupportedChannelLayout(0))
+        ));
+    }
+
+    #[test]
+    fn test_channel_mix() {
+        let mut resampler = BeatriceResampler::new(48000.0, 24000.0, 4, 4).unwrap();
+
+        assert!(matches!(
+            resampler.set_channel_mix(ChannelMix {
+                downmix: Downmix::Channel(4),
+                upmix: Upmix::DuplicateToAll,
+            }),
+            Err(BeatriceError::InvalidChannelIndex(4))
+        ));
+
+        resampler
+            .set_channel_mix(ChannelMix {
+                downmix: Downmix::Channel(3),
+                upmix: Upmix::DuplicateToFirstTwo,
+            })
+            .unwrap();
+
+        let input = [0.0, 0.0, 0.0, 1.0].repeat(480);
+        let mut mono = vec![];
+        for _ in 0..10 {
+            mono = resampler.convert_to_beatrice_input(&input).unwrap();
+        }
+        assert!(mono.iter().all(|v| (v - 1.0).abs() < 1e-2));
+
+        let output = resampler.convert_from_beatrice_output(&[1.0; 240]).unwrap();
+        for frame in output.chunks_exact(4) {
+            assert_eq!(frame[0], frame[1]);
+            assert_eq!(frame[2..], [0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn test_variable_length() {
+        let mut resampler = BeatriceResampler::new(48000.0, 44100.0, 2, 2).unwrap();
+
+        let mut total_in = 0;
+        let mut total_hop = 0;
+        for i in 0..200 {
+            let frames = [1, 37, 480, 1024, 2000][i % 5];
+            total_in += frames;
+
+            let mono = resampler
+                .convert_to_beatrice_input(&vec![0.0; frames * 2])
+                .unwrap();
+            assert_eq!(mono.len() % 160, 0);
+            total_hop += mono.len();
+        }
+        assert!(total_hop.abs_diff(total_in / 3) <= 320);
+
+        let mut total_out = 0;
+        for i in 0..200 {
+            let output = resampler
+                .convert_from_beatrice_output(&vec![0.0; [1, 100, 240, 1000][i % 4]])
+                .unwrap();
+            assert_eq!(output.len() % 2, 0);
+            total_out += output.len() / 2;
+        }
+        let expected = (1 + 100 + 240 + 1000) * 50 * 44100 / 24000;
+        assert!(total_out.abs_diff(expected) <= 480);
+    }
+
+    #[test]
+    fn test_model_delay() {
+        let mut resampler = BeatriceResampler::new(24000.0, 24000.0, 1, 1).unwrap();
+        let base = resampler.latency();
+
+        resampler.set_model_delay(240);
+        let latency = resampler.latency();
+        assert_eq!(latency.output_frames, base.output_frames + 240);
+
+        // 先頭の遅延分は出力されない
+        let mut delayed = vec![0.0; 240];
+        delayed.extend((0..2400).map(|i| i as f32));
+        let mut output = resampler.convert_from_beatrice_output(&delayed).unwrap();
+        output.extend(resampler.convert_from_beatrice_output(&[0.0; 480]).unwrap());
+        let peak = output
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap()
+            .0;
+        assert!(peak.abs_diff(2399) <= 3);
+
+        // reset で捨てる分が戻る
+        resampler.reset();
+        let output = resampler.convert_from_beatrice_output(&[1.0; 240]).unwrap();
+        assert!(output.is_empty());
     }
 }
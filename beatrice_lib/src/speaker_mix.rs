@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::BeatriceError;
+
+/// 声を混ぜるときの話者とその重み
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SpeakerWeight {
+    pub speaker: u32,
+    pub weight: f64,
+}
+
+impl SpeakerWeight {
+    pub const fn new(speaker: u32, weight: f64) -> Self {
+        Self { speaker, weight }
+    }
+}
+
+/// 同じ話者をまとめ、重み 0 を除き、合計が 1 になるようにして重い順に並べる
+///
+/// `n_speakers` 以上の話者は `BeatriceError::SpeakerOutOfRange`、負や NaN の重みと
+/// 重みの合計が 0 の場合は `BeatriceError::ParameterOutOfRange` になる
+#[cfg_attr(not(any(feature = "native", feature = "mock")), allow(dead_code))]
+pub(crate) fn normalize_speaker_mix(
+    speaker_mix: &[SpeakerWeight],
+    n_speakers: usize,
+) -> Result<Vec<SpeakerWeight>, BeatriceError> {
+    let mut merged: Vec<SpeakerWeight> = vec![];
+
+    for &SpeakerWeight { speaker, weight } in speaker_mix {
+        if n_speakers <= speaker as usize {
+            return Err(BeatriceError::SpeakerOutOfRange);
+        }
+        if !(weight.is_finite() && 0.0 <= weight) {
+            return Err(BeatriceError::ParameterOutOfRange("speaker_weight", weight));
+        }
+
+        match merged.iter_mut().find(|m| m.speaker == speaker) {
+            Some(m) => m.weight += weight,
+            None => merged.push(SpeakerWeight { speaker, weight }),
+        }
+    }
+
+    let total = merged.iter().map(|m| m.weight).sum::<f64>();
+    if total <= 0.0 {
+        return Err(BeatriceError::ParameterOutOfRange("speaker_weight", total));
+    }
+
+    merged.retain(|m| 0.0 < m.weight);
+    for m in merged.iter_mut() {
+        m.weight /= total;
+    }
+    merged.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+
+    Ok(merged)
+}
+
+/// 話者ごとに `output.len()` ずつ並んだ `embeddings` を重みで足し合わせて `output` に書く
+#[cfg_attr(not(feature = "native"), allow(dead_code))]
+pub(crate) fn blend_embeddings(
+    output: &mut [f32],
+    embeddings: &[f32],
+    speaker_mix: &[SpeakerWeight],
+) {
+    let channels = output.len();
+    output.fill(0.0);
+
+    for &SpeakerWeight { speaker, weight } in speaker_mix {
+        let start = speaker as usize * channels;
+        let embedding = &embeddings[start..start + channels];
+
+        for (out, &v) in output.iter_mut().zip(embedding) {
+            *out += v * weight as f32;
+        }
+    }
+}
+
+/// 混ぜた声の平均ピッチ。平均ピッチの分からない話者が含まれていれば `None`
+#[cfg_attr(not(any(feature = "native", feature = "mock")), allow(dead_code))]
+pub(crate) fn mixed_average_pitch(
+    speaker_mix: &[SpeakerWeight],
+    voice_average_pitches: &HashMap<u32, f64>,
+) -> Option<f64> {
+    speaker_mix.iter().try_fold(0.0, |sum, m| {
+        Some(sum + voice_average_pitches.get(&m.speaker)? * m.weight)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        BeatriceError,
+        speaker_mix::{
+            SpeakerWeight, blend_embeddings, mixed_average_pitch, normalize_speaker_mix,
+        },
+    };
+
+    #[test]
+    fn test_normalize_speaker_mix() {
+        let mix = normalize_speaker_mix(
+            &[
+                SpeakerWeight::new(0, 1.0),
+                SpeakerWeight::new(2, 0.0),
+                SpeakerWeight::new(1, 2.0),
+                SpeakerWeight::new(0, 1.0),
+            ],
+            3,
+        )
+        .unwrap();
+        assert_eq!(
+            mix,
+            [SpeakerWeight::new(0, 0.5), SpeakerWeight::new(1, 0.5)]
+        );
+
+        assert!(matches!(
+            normalize_speaker_mix(&[SpeakerWeight::new(3, 1.0)], 3),
+            Err(BeatriceError::SpeakerOutOfRange)
+        ));
+        assert!(normalize_speaker_mix(&[SpeakerWeight::new(0, -1.0)], 3).is_err());
+        assert!(normalize_speaker_mix(&[SpeakerWeight::new(0, 0.0)], 3).is_err());
+        assert!(normalize_speaker_mix(&[], 3).is_err());
+    }
+
+    #[test]
+    fn test_blend_embeddings() {
+        let embeddings = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let mut output = [0.0; 2];
+
+        blend_embeddings(&mut output, &embeddings, &[SpeakerWeight::new(1, 1.0)]);
+        assert_eq!(output, [3.0, 4.0]);
+
+        blend_embeddings(
+            &mut output,
+            &embeddings,
+            &[SpeakerWeight::new(0, 0.75), SpeakerWeight::new(2, 0.25)],
+        );
+        assert_eq!(output, [2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_mixed_average_pitch() {
+        let pitches = HashMap::from([(0, 50.0), (1, 60.0)]);
+        let mix = [SpeakerWeight::new(0, 0.25), SpeakerWeight::new(1, 0.75)];
+
+        assert_eq!(mixed_average_pitch(&mix, &pitches), Some(57.5));
+        assert_eq!(
+            mixed_average_pitch(&[SpeakerWeight::new(2, 1.0)], &pitches),
+            None
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+// 取り出されないまま溜める hop の上限 (1 秒分)。超えたら古いものから捨てる
+const MAX_PENDING_HOPS: usize = 100;
+
+/// 1 hop (10ms) 分のピッチの情報 (音高はいずれも MIDI ノート番号)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PitchTelemetry {
+    /// 入力から推定した音高。無声なら `None`
+    pub source_pitch: Option<f64>,
+    /// ピッチシフトや補正をかけた後、モデルに渡す音高。無声なら `None`
+    pub output_pitch: Option<f64>,
+    pub voiced: bool,
+}
+
+#[cfg_attr(not(any(feature = "native", feature = "mock")), allow(dead_code))]
+#[derive(Debug, Default)]
+pub(crate) struct TelemetryBuffer {
+    enabled: bool,
+    hops: VecDeque<PitchTelemetry>,
+}
+
+#[cfg_attr(not(any(feature = "native", feature = "mock")), allow(dead_code))]
+impl TelemetryBuffer {
+    /// 無効にすると溜まっていた分も捨てる
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.hops = VecDeque::new();
+        }
+    }
+
+    pub fn push(&mut self, telemetry: PitchTelemetry) {
+        if !self.enabled {
+            return;
+        }
+
+        if MAX_PENDING_HOPS <= self.hops.len() {
+            self.hops.pop_front();
+        }
+        self.hops.push_back(telemetry);
+    }
+
+    pub fn take(&mut self) -> Vec<PitchTelemetry> {
+        self.hops.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::telemetry::{MAX_PENDING_HOPS, PitchTelemetry, TelemetryBuffer};
+
+    const HOP: PitchTelemetry = PitchTelemetry {
+        source_pitch: Some(57.0),
+        output_pitch: Some(69.0),
+        voiced: true,
+    };
+
+    #[test]
+    fn test_telemetry_buffer() {
+        let mut buffer = TelemetryBuffer::default();
+
+        // 無効の間は溜めない
+        buffer.push(HOP);
+        assert!(buffer.take().is_empty());
+
+        buffer.set_enabled(true);
+        for _ in 0..MAX_PENDING_HOPS + 10 {
+            buffer.push(HOP);
+        }
+        assert_eq!(buffer.take().len(), MAX_PENDING_HOPS);
+        assert!(buffer.take().is_empty());
+
+        buffer.push(HOP);
+        buffer.set_enabled(false);
+        assert!(buffer.take().is_empty());
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+
+use crate::{
+    calibration::{CalibrationState, PitchCalibration, PitchCalibrator},
+    constants::BEATRICE_IN_HOP_LENGTH,
+    errors::BeatriceError,
+    parameters::{
+        Capabilities, PitchCorrectionMode, VoiceParameters, auto_pitch_shift, validate_supported,
+    },
+    pitch::{self, PitchParameters, PitchState},
+    speaker_mix::{SpeakerWeight, mixed_average_pitch, normalize_speaker_mix},
+    telemetry::{PitchTelemetry, TelemetryBuffer},
+};
+
+/// どのバージョンのモデルでも共通の声の設定と、hop ごとのピッチの処理
+///
+/// 設定の検証、自動のピッチシフト、キャリブレーション、テレメトリはここにまとめ、
+/// 各バックエンドはこれを持ってモデル固有の処理だけを足す
+#[derive(Debug)]
+pub struct VoiceState {
+    capabilities: Capabilities,
+
+    pub target_speaker: u32,
+    pub speaker_mix: Vec<SpeakerWeight>,
+    pub formant_shift: f64,
+    pub pitch_shift: f64,
+    pub auto_pitch_shift: bool,
+    pub n_speakers: i32,
+    pub average_source_pitch: f64,
+    pub intonation_intensity: f64,
+    pub pitch_correction: f64,
+    pub pitch_correction_mode: PitchCorrectionMode,
+    pub pitch_glide_time: f64,
+    pub pitch_median_window: u32,
+    pub pitch_hysteresis: f64,
+    pub min_source_pitch: Option<f64>,
+    pub max_source_pitch: Option<f64>,
+    pub vq_num_neighbors: Option<i32>,
+    /// 話者 ID ごとの平均ピッチ (モデルの toml の `average_pitch`)
+    pub voice_average_pitches: HashMap<u32, f64>,
+
+    calibrator: PitchCalibrator,
+    telemetry: TelemetryBuffer,
+    pitch_state: PitchState,
+}
+
+impl VoiceState {
+    /// 設定の初期値は `capabilities` の `default` にする
+    pub(crate) fn new(capabilities: Capabilities) -> Self {
+        Self {
+            capabilities,
+            target_speaker: 0,
+            speaker_mix: vec![SpeakerWeight::new(0, 1.0)],
+            formant_shift: capabilities.formant_shift.default,
+            pitch_shift: capabilities.pitch_shift.default,
+            auto_pitch_shift: false,
+            n_speakers: 0,
+            average_source_pitch: capabilities.average_source_pitch.default,
+            intonation_intensity: capabilities.intonation_intensity.default,
+            pitch_correction: capabilities.pitch_correction.default,
+            pitch_correction_mode: PitchCorrectionMode::Off,
+            pitch_glide_time: capabilities.pitch_glide_time.default,
+            pitch_median_window: capabilities.pitch_median_window.default as u32,
+            pitch_hysteresis: capabilities.pitch_hysteresis.default,
+            min_source_pitch: capabilities.min_source_pitch.map(|r| r.default),
+            max_source_pitch: capabilities.max_source_pitch.map(|r| r.default),
+            vq_num_neighbors: capabilities.vq_num_neighbors.map(|r| r.default as i32),
+            voice_average_pitches: HashMap::new(),
+            calibrator: PitchCalibrator::default(),
+            telemetry: TelemetryBuffer::default(),
+            pitch_state: PitchState::default(),
+        }
+    }
+
+    pub(crate) fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    pub(crate) fn parameters(&self) -> VoiceParameters {
+        VoiceParameters {
+            target_speaker: self.target_speaker,
+            speaker_mix: self.speaker_mix.clone(),
+            pitch_shift: self.pitch_shift,
+            auto_pitch_shift: self.auto_pitch_shift,
+            formant_shift: self.formant_shift,
+            average_source_pitch: self.average_source_pitch,
+            intonation_intensity: self.intonation_intensity,
+            pitch_correction: self.pitch_correction,
+            pitch_correction_mode: self.pitch_correction_mode,
+            pitch_glide_time: self.pitch_glide_time,
+            pitch_median_window: self.pitch_median_window,
+            pitch_hysteresis: self.pitch_hysteresis,
+            min_source_pitch: self.min_source_pitch,
+            max_source_pitch: self.max_source_pitch,
+            vq_num_neighbors: self.vq_num_neighbors,
+        }
+    }
+
+    /// 推論の内部状態だけを捨てる。設定はそのまま
+    pub(crate) fn reset(&mut self) {
+        self.pitch_state.clear();
+    }
+
+    /// 推定した quantized pitch をキャリブレーションに渡し、ピッチシフトや補正、平滑化を掛けて
+    /// モデルに渡す quantized pitch を返す。テレメトリが有効なら hop ごとに記録する
+    pub(crate) fn process_pitch(
+        &mut self,
+        input: &[f32; BEATRICE_IN_HOP_LENGTH as usize],
+        quantized_pitch: i32,
+        pitch_bins: u32,
+    ) -> i32 {
+        // Calibration
+        let note =
+            (1 <= quantized_pitch).then(|| pitch::quantized_pitch_to_note(quantized_pitch as f64));
+        self.calibrator.push(input, note, &self.capabilities);
+
+        let parameters = PitchParameters {
+            average_source_pitch: self.average_source_pitch,
+            intonation_intensity: self.intonation_intensity,
+            pitch_shift: self.pitch_shift,
+            pitch_correction: self.pitch_correction,
+            pitch_correction_mode: self.pitch_correction_mode,
+            pitch_glide_time: self.pitch_glide_time,
+            pitch_median_window: self.pitch_median_window,
+            pitch_hysteresis: self.pitch_hysteresis,
+        };
+        let quantized_pitch = pitch::process_pitch(
+            quantized_pitch,
+            pitch_bins,
+            &parameters,
+            &mut self.pitch_state,
+        );
+
+        // Telemetry
+        self.telemetry.push(PitchTelemetry {
+            source_pitch: note,
+            output_pitch: note.map(|_| pitch::quantized_pitch_to_note(quantized_pitch as f64)),
+            voiced: note.is_some(),
+        });
+
+        quantized_pitch
+    }
+
+    /// 自動のピッチシフトが有効なら、ターゲットの声の平均ピッチに合わせてピッチシフトを決め直す
+    fn apply_auto_pitch_shift(&mut self) {
+        if !self.auto_pitch_shift {
+            return;
+        }
+
+        let Some(average_target_pitch) =
+            mixed_average_pitch(&self.speaker_mix, &self.voice_average_pitches)
+        else {
+            return;
+        };
+
+        self.pitch_shift = auto_pitch_shift(
+            self.average_source_pitch,
+            average_target_pitch,
+            self.capabilities.pitch_shift,
+        );
+    }
+
+    pub(crate) fn start_pitch_calibration(&mut self, seconds: f64) -> Result<(), BeatriceError> {
+        self.calibrator.start(seconds)
+    }
+
+    pub(crate) fn get_pitch_calibration(&self) -> CalibrationState {
+        self.calibrator.state()
+    }
+
+    pub(crate) fn finish_pitch_calibration(&mut self) -> Option<PitchCalibration> {
+        self.calibrator.finish(&self.capabilities)
+    }
+
+    pub(crate) fn set_pitch_telemetry(&mut self, enabled: bool) {
+        self.telemetry.set_enabled(enabled);
+    }
+
+    pub(crate) fn take_pitch_telemetry(&mut self) -> Vec<PitchTelemetry> {
+        self.telemetry.take()
+    }
+
+    /// `n_speakers` はモデルが持つ話者埋め込みの数
+    pub(crate) fn set_speaker_mix(
+        &mut self,
+        speaker_mix: &[SpeakerWeight],
+        n_speakers: usize,
+    ) -> Result<(), BeatriceError> {
+        let speaker_mix = normalize_speaker_mix(speaker_mix, n_speakers)?;
+        self.store_speaker_mix(speaker_mix);
+        Ok(())
+    }
+
+    /// `normalize_speaker_mix` を通した混ぜ方を設定に入れる
+    ///
+    /// モデルに埋め込みを渡してから設定を変えたいバックエンドは、検証とこれを分けて呼ぶ
+    pub(crate) fn store_speaker_mix(&mut self, speaker_mix: Vec<SpeakerWeight>) {
+        self.target_speaker = speaker_mix[0].speaker;
+        self.speaker_mix = speaker_mix;
+        self.apply_auto_pitch_shift();
+    }
+
+    pub(crate) fn set_formant_shift(&mut self, formant_shift: f64) -> Result<(), BeatriceError> {
+        self.formant_shift = self
+            .capabilities
+            .formant_shift
+            .validate("formant_shift", formant_shift)?;
+        Ok(())
+    }
+
+    /// 手で決めたピッチシフトを後から上書きしないように、自動のピッチシフトは無効にする
+    pub(crate) fn set_pitch_shift(&mut self, pitch_shift: f64) -> Result<(), BeatriceError> {
+        self.pitch_shift = self
+            .capabilities
+            .pitch_shift
+            .validate("pitch_shift", pitch_shift)?;
+        self.auto_pitch_shift = false;
+        Ok(())
+    }
+
+    pub(crate) fn set_auto_pitch_shift(&mut self, auto_pitch_shift: bool) {
+        self.auto_pitch_shift = auto_pitch_shift;
+        self.apply_auto_pitch_shift();
+    }
+
+    pub(crate) fn set_average_source_pitch(
+        &mut self,
+        average_source_pitch: f64,
+    ) -> Result<(), BeatriceError> {
+        self.average_source_pitch = self
+            .capabilities
+            .average_source_pitch
+            .validate("average_source_pitch", average_source_pitch)?;
+        self.apply_auto_pitch_shift();
+        Ok(())
+    }
+
+    pub(crate) fn set_intonation_intensity(
+        &mut self,
+        intonation_intensity: f64,
+    ) -> Result<(), BeatriceError> {
+        self.intonation_intensity = self
+            .capabilities
+            .intonation_intensity
+            .validate("intonation_intensity", intonation_intensity)?;
+        Ok(())
+    }
+
+    pub(crate) fn set_pitch_correction(
+        &mut self,
+        pitch_correction: f64,
+    ) -> Result<(), BeatriceError> {
+        self.pitch_correction = self
+            .capabilities
+            .pitch_correction
+            .validate("pitch_correction", pitch_correction)?;
+        Ok(())
+    }
+
+    pub(crate) fn set_pitch_correction_mode(
+        &mut self,
+        pitch_correction_mode: PitchCorrectionMode,
+    ) -> Result<(), BeatriceError> {
+        pitch_correction_mode.validate(&self.capabilities)?;
+
+        self.pitch_correction_mode = pitch_correction_mode;
+        Ok(())
+    }
+
+    pub(crate) fn set_pitch_glide_time(
+        &mut self,
+        pitch_glide_time: f64,
+    ) -> Result<(), BeatriceError> {
+        self.pitch_glide_time = self
+            .capabilities
+            .pitch_glide_time
+            .validate("pitch_glide_time", pitch_glide_time)?;
+        Ok(())
+    }
+
+    pub(crate) fn set_pitch_median_window(
+        &mut self,
+        pitch_median_window: u32,
+    ) -> Result<(), BeatriceError> {
+        self.capabilities
+            .pitch_median_window
+            .validate("pitch_median_window", pitch_median_window as f64)?;
+
+        self.pitch_median_window = pitch_median_window;
+        Ok(())
+    }
+
+    pub(crate) fn set_pitch_hysteresis(
+        &mut self,
+        pitch_hysteresis: f64,
+    ) -> Result<(), BeatriceError> {
+        self.pitch_hysteresis = self
+            .capabilities
+            .pitch_hysteresis
+            .validate("pitch_hysteresis", pitch_hysteresis)?;
+        Ok(())
+    }
+
+    pub(crate) fn set_min_source_pitch(
+        &mut self,
+        min_source_pitch: f64,
+    ) -> Result<(), BeatriceError> {
+        let range = self.capabilities.min_source_pitch;
+        self.min_source_pitch = Some(validate_supported(
+            range,
+            "min_source_pitch",
+            min_source_pitch,
+        )?);
+        Ok(())
+    }
+
+    pub(crate) fn set_max_source_pitch(
+        &mut self,
+        max_source_pitch: f64,
+    ) -> Result<(), BeatriceError> {
+        let range = self.capabilities.max_source_pitch;
+        self.max_source_pitch = Some(validate_supported(
+            range,
+            "max_source_pitch",
+            max_source_pitch,
+        )?);
+        Ok(())
+    }
+
+    pub(crate) fn set_vq_num_neighbors(
+        &mut self,
+        vq_num_neighbors: i32,
+    ) -> Result<(), BeatriceError> {
+        let range = self.capabilities.vq_num_neighbors;
+        validate_supported(range, "vq_num_neighbors", vq_num_neighbors as f64)?;
+
+        self.vq_num_neighbors = Some(vq_num_neighbors);
+        Ok(())
+    }
+}